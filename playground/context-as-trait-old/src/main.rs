@@ -4,18 +4,28 @@
 mod context {
     use std::{
         any::{Any, TypeId},
+        cell::UnsafeCell,
         collections::HashMap,
     };
 
+    type Plan = Box<dyn FnOnce(&mut BaseContext)>;
+
     pub struct BaseContext {
-        data_containers: HashMap<TypeId, Box<dyn Any>>,
-        plans: Vec<Box<dyn FnOnce(&mut Self)>>,
+        // Data containers are created lazily, possibly through a shared reference
+        data_containers: UnsafeCell<HashMap<TypeId, Box<dyn Any>>>,
+        plans: Vec<Plan>,
+    }
+
+    impl Default for BaseContext {
+        fn default() -> Self {
+            Self::new()
+        }
     }
 
     impl BaseContext {
         pub fn new() -> Self {
             BaseContext {
-                data_containers: HashMap::new(),
+                data_containers: UnsafeCell::new(HashMap::new()),
                 plans: Vec::new(),
             }
         }
@@ -24,8 +34,9 @@ mod context {
     pub trait Plugin: 'static {
         type DataContainer;
 
-        // TODO: Make this init of &Context
-        fn default() -> Self::DataContainer;
+        /// Creates the data container the first time the plugin is accessed,
+        /// which may read other plugins.
+        fn init(context: &impl Context) -> Self::DataContainer;
     }
 
     pub trait Context {
@@ -36,16 +47,42 @@ mod context {
     }
     impl Context for BaseContext {
         fn plugin_data<P: Plugin>(&self) -> &P::DataContainer {
-            self.data_containers
-                .get(&TypeId::of::<P>())
-                .and_then(|data| data.downcast_ref::<P::DataContainer>())
+            let type_id = TypeId::of::<P>();
+            // SAFETY: the insert below can run while references returned by
+            //  earlier calls are alive, including ones `P::init` took for its
+            //  dependencies. Those borrow the contents of the boxes, which stay
+            //  put when the table grows, and `or_insert_with` leaves an existing
+            //  entry (say, one a recursive `init` added) untouched. The `get`
+            //  borrow of the map itself ends before `P::init` runs.
+            let data = match unsafe { (*self.data_containers.get()).get(&type_id) } {
+                Some(data) => data,
+                None => {
+                    // The initializer may access (and initialize) other plugins,
+                    // so it has to run before we touch the map
+                    let data = P::init(self);
+                    unsafe {
+                        (*self.data_containers.get())
+                            .entry(type_id)
+                            .or_insert_with(|| Box::new(data))
+                    }
+                }
+            };
+            data.downcast_ref::<P::DataContainer>()
                 .expect("Failed to downcast data container")
         }
         fn plugin_data_mut<P: Plugin>(&mut self) -> &mut P::DataContainer {
+            let type_id = TypeId::of::<P>();
+            if !self.data_containers.get_mut().contains_key(&type_id) {
+                let data = P::init(self);
+                self.data_containers
+                    .get_mut()
+                    .entry(type_id)
+                    .or_insert_with(|| Box::new(data));
+            }
             self.data_containers
-                .entry(TypeId::of::<P>())
-                .or_insert_with(|| Box::new(P::default()))
-                .downcast_mut::<P::DataContainer>()
+                .get_mut()
+                .get_mut(&type_id)
+                .and_then(|data| data.downcast_mut::<P::DataContainer>())
                 .expect("Failed to downcast data container")
         }
         fn execute(&mut self) {
@@ -71,7 +108,7 @@ mod bool_plugin {
     pub struct BoolPlugin;
     impl Plugin for BoolPlugin {
         type DataContainer = bool;
-        fn default() -> Self::DataContainer {
+        fn init(_context: &impl Context) -> Self::DataContainer {
             false
         }
     }
@@ -93,7 +130,7 @@ mod number_plugin {
     pub struct NumberPlugin;
     impl Plugin for NumberPlugin {
         type DataContainer = u32;
-        fn default() -> Self::DataContainer {
+        fn init(_context: &impl Context) -> Self::DataContainer {
             0
         }
     }
@@ -131,10 +168,12 @@ use number_plugin::*;
 
 fn main() {
     let mut context = build_context!();
+    // Plugins are initialized on first access, even through a shared reference
+    assert_eq!(context.get_number(), 0);
     context.set_number(42);
     assert_eq!(context.get_number(), 42);
     context.set_bool(true);
-    assert_eq!(context.get_bool(), true);
+    assert!(context.get_bool());
     assert_eq!(context.get_bool_as_number(), 1);
     do_stuff_with_numbers(&context);
     context.add_plan(1.0, |ctx| {
@@ -3,67 +3,971 @@
 
 mod context {
     use std::{
-        any::{Any, TypeId},
-        collections::HashMap,
+        any::{Any, TypeId, type_name},
+        cell::{RefCell, UnsafeCell},
+        cmp::Ordering,
+        collections::{BTreeMap, HashMap, HashSet, VecDeque},
+        fmt, fs,
+        io::{self, BufWriter, Write},
+        panic::Location,
+        path::Path,
+        rc::Rc,
+        time::Instant,
     };
 
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum ContextError {
+        /// The plugin's initializer (directly or indirectly) accessed the plugin itself
+        ReentrantInitialization { plugin: &'static str },
+        /// `fork` found data of a plugin that wasn't registered with `register_clone`
+        UnforkableData { plugin: &'static str },
+        /// `fork` found a queued plan that wasn't added with `add_forkable_plan`
+        UnforkablePlan { plan: String },
+    }
+
+    impl fmt::Display for ContextError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                ContextError::ReentrantInitialization { plugin } => write!(
+                    f,
+                    "Plugin `{plugin}` was accessed while it was being initialized"
+                ),
+                ContextError::UnforkableData { plugin } => write!(
+                    f,
+                    "Plugin `{plugin}` has no clone function, so the context can't be forked"
+                ),
+                ContextError::UnforkablePlan { plan } => write!(
+                    f,
+                    "Plan `{plan}` can't be cloned, so the context can't be forked"
+                ),
+            }
+        }
+    }
+
+    impl std::error::Error for ContextError {}
+
+    /// A plan or callback. Forking a context copies its queued plans, which
+    /// only works for plans that were added as forkable.
+    trait PlanFn {
+        fn call(self: Box<Self>, context: &mut Context);
+        fn try_clone(&self) -> Option<Box<dyn PlanFn>>;
+    }
+
+    struct OncePlan<F>(F);
+
+    impl<F: FnOnce(&mut Context) + 'static> PlanFn for OncePlan<F> {
+        fn call(self: Box<Self>, context: &mut Context) {
+            (self.0)(context);
+        }
+        fn try_clone(&self) -> Option<Box<dyn PlanFn>> {
+            None
+        }
+    }
+
+    struct ForkablePlan<F>(F);
+
+    impl<F: FnOnce(&mut Context) + Clone + 'static> PlanFn for ForkablePlan<F> {
+        fn call(self: Box<Self>, context: &mut Context) {
+            (self.0)(context);
+        }
+        fn try_clone(&self) -> Option<Box<dyn PlanFn>> {
+            Some(Box::new(ForkablePlan(self.0.clone())))
+        }
+    }
+
+    // Copies a plugin's data into a forked context
+    type CloneData = fn(&dyn Any) -> Box<dyn Any>;
+
+    fn clone_data<P: Plugin>(data: &dyn Any) -> Box<dyn Any>
+    where
+        P::DataContainer: Clone,
+    {
+        Box::new(data.downcast_ref::<P::DataContainer>().unwrap().clone())
+    }
+
+    /// `Any` for event handler lists, which are copied into forked contexts.
+    trait CloneAny: Any {
+        fn clone_box(&self) -> Box<dyn CloneAny>;
+    }
+
+    impl<T: Any + Clone> CloneAny for T {
+        fn clone_box(&self) -> Box<dyn CloneAny> {
+            Box::new(self.clone())
+        }
+    }
+
+    impl dyn CloneAny {
+        fn downcast_ref<T: Any>(&self) -> Option<&T> {
+            (self as &dyn Any).downcast_ref()
+        }
+        fn downcast_mut<T: Any>(&mut self) -> Option<&mut T> {
+            (self as &mut dyn Any).downcast_mut()
+        }
+    }
+
+    type Callback = Box<dyn PlanFn>;
+    type EventHandler<E> = Rc<dyn Fn(&mut Context, E)>;
+
+    /// Plans scheduled for the same time run in phase order.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum ExecutionPhase {
+        First,
+        Normal,
+        Last,
+    }
+
+    /// Orders plans by time, then phase, then the order they were added in.
+    #[derive(Debug, Clone, Copy)]
+    struct PlanKey {
+        time: f64,
+        phase: ExecutionPhase,
+        id: usize,
+    }
+    impl PartialEq for PlanKey {
+        fn eq(&self, other: &Self) -> bool {
+            self.cmp(other) == Ordering::Equal
+        }
+    }
+    impl Eq for PlanKey {}
+    impl PartialOrd for PlanKey {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+    impl Ord for PlanKey {
+        fn cmp(&self, other: &Self) -> Ordering {
+            self.time
+                .total_cmp(&other.time)
+                .then(self.phase.cmp(&other.phase))
+                .then(self.id.cmp(&other.id))
+        }
+    }
+
+    /// Where a plan was added, and its label if it has one.
+    #[derive(Debug, Clone)]
+    struct PlanInfo {
+        label: Option<String>,
+        location: &'static Location<'static>,
+    }
+
+    impl fmt::Display for PlanInfo {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match &self.label {
+                Some(label) => write!(f, "{label}"),
+                None => write!(f, "{}", self.location),
+            }
+        }
+    }
+
+    /// One line of an execution trace.
+    #[derive(serde::Serialize)]
+    struct TraceRecord<'a> {
+        time: f64,
+        label: Option<&'a str>,
+        location: String,
+        duration_ns: u128,
+    }
+
     pub struct Context {
-        data_containers: HashMap<TypeId, Box<dyn Any>>,
-        plans: Vec<Box<dyn FnOnce(&mut Self)>>,
+        // Data containers are created lazily, possibly through a shared reference
+        data_containers: UnsafeCell<HashMap<TypeId, Box<dyn Any>>>,
+        // Names of the plugins in `data_containers`, for errors
+        plugin_names: RefCell<HashMap<TypeId, &'static str>>,
+        // Plugins whose initializer is currently running
+        initializing: RefCell<HashSet<TypeId>>,
+        // Plugins registered with `register_clone`
+        clone_fns: HashMap<TypeId, CloneData>,
+        plans: BTreeMap<PlanKey, Callback>,
+        // Labels and source locations of pending plans, by plan id
+        plan_info: HashMap<usize, PlanInfo>,
+        next_plan_id: usize,
+        current_time: f64,
+        callbacks: VecDeque<Callback>,
+        // Each entry is a Vec<EventHandler<E>> keyed by the TypeId of E
+        event_handlers: HashMap<TypeId, Box<dyn CloneAny>>,
+        // Every executed plan is logged here if set
+        trace: Option<Box<dyn Write>>,
+    }
+
+    impl Default for Context {
+        fn default() -> Self {
+            Self::new()
+        }
     }
 
     impl Context {
         pub fn new() -> Self {
             Context {
-                data_containers: HashMap::new(),
-                plans: Vec::new(),
+                data_containers: UnsafeCell::new(HashMap::new()),
+                plugin_names: RefCell::new(HashMap::new()),
+                initializing: RefCell::new(HashSet::new()),
+                clone_fns: HashMap::new(),
+                plans: BTreeMap::new(),
+                plan_info: HashMap::new(),
+                next_plan_id: 0,
+                current_time: 0.0,
+                callbacks: VecDeque::new(),
+                event_handlers: HashMap::new(),
+                trace: None,
+            }
+        }
+        /// Lets `fork` copy the data of `P`.
+        pub fn register_clone<P: Plugin>(&mut self)
+        where
+            P::DataContainer: Clone,
+        {
+            self.clone_fns.insert(TypeId::of::<P>(), clone_data::<P>);
+        }
+        /// Returns a copy of the context, including plugin data and pending
+        /// plans, to explore an alternative from the current time on. Every
+        /// initialized plugin must be registered with `register_clone` and
+        /// every pending plan added with `add_forkable_plan`.
+        ///
+        /// Event handlers are not copied but shared with the fork, so any state
+        /// they capture (e.g. through an `Rc<RefCell<_>>`) is shared too, and
+        /// the original and the fork both update it. The fork is not traced.
+        pub fn fork(&self) -> Result<Context, ContextError> {
+            // Safety: initializers (the only code that inserts through a shared
+            //  reference) can't run while the map is being copied
+            let data_containers = unsafe { &*self.data_containers.get() };
+            let plugin_names = self.plugin_names.borrow();
+            let mut forked_data = HashMap::new();
+            for (type_id, data) in data_containers {
+                let clone =
+                    self.clone_fns
+                        .get(type_id)
+                        .ok_or_else(|| ContextError::UnforkableData {
+                            plugin: plugin_names[type_id],
+                        })?;
+                forked_data.insert(*type_id, clone(data.as_ref()));
             }
+            let mut plans = BTreeMap::new();
+            for (key, plan) in &self.plans {
+                let plan = plan
+                    .try_clone()
+                    .ok_or_else(|| ContextError::UnforkablePlan {
+                        plan: self.plan_info[&key.id].to_string(),
+                    })?;
+                plans.insert(*key, plan);
+            }
+            Ok(Context {
+                data_containers: UnsafeCell::new(forked_data),
+                plugin_names: RefCell::new(plugin_names.clone()),
+                initializing: RefCell::new(HashSet::new()),
+                clone_fns: self.clone_fns.clone(),
+                plans,
+                plan_info: self.plan_info.clone(),
+                next_plan_id: self.next_plan_id,
+                current_time: self.current_time,
+                // Only event handler deliveries, which can always be cloned
+                callbacks: self
+                    .callbacks
+                    .iter()
+                    .map(|callback| callback.try_clone().unwrap())
+                    .collect(),
+                event_handlers: self
+                    .event_handlers
+                    .iter()
+                    .map(|(type_id, handlers)| (*type_id, (**handlers).clone_box()))
+                    .collect(),
+                trace: None,
+            })
+        }
+        /// Logs every plan executed from now on to `writer`, as one JSON object
+        /// per line with its time, label, source location and duration.
+        pub fn trace_to(&mut self, writer: impl Write + 'static) {
+            self.trace = Some(Box::new(writer));
+        }
+        /// Like `trace_to`, writing to a new file at `path`.
+        pub fn trace_to_file(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+            self.trace_to(BufWriter::new(fs::File::create(path)?));
+            Ok(())
+        }
+        fn run_plan(&mut self, key: PlanKey, plan: Callback) {
+            self.current_time = key.time;
+            let info = self.plan_info.remove(&key.id).unwrap();
+            if self.trace.is_none() {
+                plan.call(self);
+                return;
+            }
+            let start = Instant::now();
+            plan.call(self);
+            let duration = start.elapsed();
+            let record = TraceRecord {
+                time: key.time,
+                label: info.label.as_deref(),
+                location: info.location.to_string(),
+                duration_ns: duration.as_nanos(),
+            };
+            // The plan may have replaced the trace, so check again
+            if let Some(trace) = &mut self.trace {
+                serde_json::to_writer(&mut *trace, &record)
+                    .map_err(io::Error::from)
+                    .and_then(|()| writeln!(trace))
+                    .expect("Failed to write trace");
+            }
+        }
+        fn flush_trace(&mut self) {
+            if let Some(trace) = &mut self.trace {
+                trace.flush().expect("Failed to write trace");
+            }
+        }
+        /// Like `execute`, but lets `debugger` pause before plans. Stops early
+        /// (leaving plans queued) if the debugger quits or its I/O fails.
+        pub fn execute_with_debugger<R: io::BufRead, W: Write>(
+            &mut self,
+            debugger: &mut debugger::Debugger<R, W>,
+        ) -> io::Result<()> {
+            loop {
+                if let Some(callback) = self.callbacks.pop_front() {
+                    callback.call(self);
+                    continue;
+                }
+                let Some(key) = self.plans.keys().next().copied() else {
+                    break;
+                };
+                let label = self.plan_info[&key.id].label.as_deref();
+                if debugger.should_pause(key.time, label) && !debugger.pause(self)? {
+                    break;
+                }
+                let plan = self.plans.remove(&key).unwrap();
+                self.run_plan(key, plan);
+            }
+            self.flush_trace();
+            Ok(())
         }
         pub fn execute(&mut self) {
             println!("Executing!");
-            while let Some(plan) = self.plans.pop() {
-                plan(self);
+            loop {
+                // Callbacks (e.g. event handlers) always run before the next plan
+                if let Some(callback) = self.callbacks.pop_front() {
+                    callback.call(self);
+                } else if let Some((key, plan)) = self.plans.pop_first() {
+                    self.run_plan(key, plan);
+                } else {
+                    break;
+                }
             }
+            self.flush_trace();
         }
-        fn plugin_data<P: Plugin>(&self) -> &P::DataContainer {
-            self.data_containers
-                .get(&TypeId::of::<P>())
-                .and_then(|data| data.downcast_ref::<P::DataContainer>())
-                .expect("Failed to downcast data container")
+        fn init_plugin_data<P: Plugin>(&self) -> Result<P::DataContainer, ContextError> {
+            let type_id = TypeId::of::<P>();
+            if !self.initializing.borrow_mut().insert(type_id) {
+                return Err(ContextError::ReentrantInitialization {
+                    plugin: type_name::<P>(),
+                });
+            }
+            let data = P::init(self);
+            self.initializing.borrow_mut().remove(&type_id);
+            self.plugin_names
+                .borrow_mut()
+                .insert(type_id, type_name::<P>());
+            Ok(data)
         }
-        fn plugin_data_mut<P: Plugin>(&mut self) -> &mut P::DataContainer {
-            self.data_containers
-                .entry(TypeId::of::<P>())
-                .or_insert_with(|| Box::new(P::default()))
-                .downcast_mut::<P::DataContainer>()
-                .expect("Failed to downcast data container")
+        fn try_plugin_data<P: Plugin>(&self) -> Result<&P::DataContainer, ContextError> {
+            let type_id = TypeId::of::<P>();
+            // SAFETY: `P::init` below takes `&Context` and may return here for
+            //  other plugins, so when we insert, `&DataContainer`s from those
+            //  nested calls (and any the caller already holds) are alive. They
+            //  point into the `Box<dyn Any>` values, not the table: growing the
+            //  table moves the boxes, not their contents. `or_insert_with` never
+            //  overwrites an entry, and `fork` and the debugger only read the map
+            //  while no initializer runs.
+            let data = match unsafe { (*self.data_containers.get()).get(&type_id) } {
+                Some(data) => data,
+                None => {
+                    // The initializer may access (and initialize) other plugins,
+                    // so it has to run before we touch the map
+                    let data = self.init_plugin_data::<P>()?;
+                    unsafe {
+                        (*self.data_containers.get())
+                            .entry(type_id)
+                            .or_insert_with(|| Box::new(data))
+                    }
+                }
+            };
+            Ok(data
+                .downcast_ref::<P::DataContainer>()
+                .expect("Failed to downcast data container"))
         }
-        fn add_plan(&mut self, time: f64, plan: impl FnOnce(&mut Self) + 'static) {
-            self.plans.push(Box::new(plan));
+        fn try_plugin_data_mut<P: Plugin>(
+            &mut self,
+        ) -> Result<&mut P::DataContainer, ContextError> {
+            let type_id = TypeId::of::<P>();
+            if !self.data_containers.get_mut().contains_key(&type_id) {
+                let data = self.init_plugin_data::<P>()?;
+                self.data_containers
+                    .get_mut()
+                    .entry(type_id)
+                    .or_insert_with(|| Box::new(data));
+            }
+            Ok(self
+                .data_containers
+                .get_mut()
+                .get_mut(&type_id)
+                .and_then(|data| data.downcast_mut::<P::DataContainer>())
+                .expect("Failed to downcast data container"))
+        }
+        pub fn get_current_time(&self) -> f64 {
+            self.current_time
+        }
+        #[track_caller]
+        fn add_plan_with_phase(
+            &mut self,
+            time: f64,
+            phase: ExecutionPhase,
+            plan: impl FnOnce(&mut Self) + 'static,
+        ) {
+            self.insert_plan(time, phase, Box::new(OncePlan(plan)));
+        }
+        #[track_caller]
+        fn add_forkable_plan_with_phase(
+            &mut self,
+            time: f64,
+            phase: ExecutionPhase,
+            plan: impl FnOnce(&mut Self) + Clone + 'static,
+        ) {
+            self.insert_plan(time, phase, Box::new(ForkablePlan(plan)));
+        }
+        #[track_caller]
+        fn insert_plan(&mut self, time: f64, phase: ExecutionPhase, plan: Callback) {
+            assert!(
+                time >= self.current_time,
+                "Time {time} is in the past (current time is {})",
+                self.current_time
+            );
+            let key = PlanKey {
+                time,
+                phase,
+                id: self.next_plan_id,
+            };
+            self.next_plan_id += 1;
+            self.plans.insert(key, plan);
+            let info = PlanInfo {
+                label: None,
+                location: Location::caller(),
+            };
+            self.plan_info.insert(key.id, info);
+        }
+        /// Adds a plan with a label, which shows up in the debugger and in
+        /// traces instead of the plan's source location.
+        #[track_caller]
+        pub fn add_labeled_plan(
+            &mut self,
+            time: f64,
+            label: impl Into<String>,
+            plan: impl FnOnce(&mut Self) + 'static,
+        ) {
+            let id = self.next_plan_id;
+            self.add_plan_with_phase(time, ExecutionPhase::Normal, plan);
+            self.plan_info.get_mut(&id).unwrap().label = Some(label.into());
+        }
+        fn queue_callback(&mut self, callback: impl FnOnce(&mut Self) + Clone + 'static) {
+            self.callbacks.push_back(Box::new(ForkablePlan(callback)));
+        }
+        fn subscribe_to_event<E: Copy + 'static>(
+            &mut self,
+            handler: impl Fn(&mut Self, E) + 'static,
+        ) {
+            self.event_handlers
+                .entry(TypeId::of::<E>())
+                .or_insert_with(|| Box::<Vec<EventHandler<E>>>::default())
+                .downcast_mut::<Vec<EventHandler<E>>>()
+                .expect("Failed to downcast event handlers")
+                .push(Rc::new(handler));
+        }
+        fn emit_event<E: Copy + 'static>(&mut self, event: E) {
+            let Some(handlers) = self.event_handlers.get(&TypeId::of::<E>()) else {
+                return;
+            };
+            let handlers = handlers
+                .downcast_ref::<Vec<EventHandler<E>>>()
+                .expect("Failed to downcast event handlers")
+                .clone();
+            // Handlers are delivered through the callback queue so that they
+            // get exclusive access to the context
+            for handler in handlers {
+                self.queue_callback(move |context| handler(context, event));
+            }
         }
     }
 
     pub trait Plugin: 'static {
         type DataContainer;
-        // TODO: Make this init of &Context
-        fn default() -> Self::DataContainer;
+        /// Creates the data container the first time the plugin is accessed,
+        /// which may read other plugins.
+        fn init(context: &Context) -> Self::DataContainer;
     }
 
     pub trait PluginContext {
-        fn plugin_data<P: Plugin>(&self) -> &P::DataContainer;
-        fn plugin_data_mut<P: Plugin>(&mut self) -> &mut P::DataContainer;
-
-        fn add_plan(&mut self, time: f64, plan: impl FnOnce(&mut Self) + 'static);
-    }
-    impl PluginContext for Context {
+        fn try_plugin_data<P: Plugin>(&self) -> Result<&P::DataContainer, ContextError>;
+        fn try_plugin_data_mut<P: Plugin>(&mut self)
+        -> Result<&mut P::DataContainer, ContextError>;
         fn plugin_data<P: Plugin>(&self) -> &P::DataContainer {
-            self.plugin_data::<P>()
+            self.try_plugin_data::<P>()
+                .unwrap_or_else(|error| panic!("{error}"))
         }
         fn plugin_data_mut<P: Plugin>(&mut self) -> &mut P::DataContainer {
-            self.plugin_data_mut::<P>()
+            self.try_plugin_data_mut::<P>()
+                .unwrap_or_else(|error| panic!("{error}"))
         }
+
+        fn get_current_time(&self) -> f64;
+        /// Plans remember where they were added, for the debugger and traces.
+        #[track_caller]
+        fn add_plan_with_phase(
+            &mut self,
+            time: f64,
+            phase: ExecutionPhase,
+            plan: impl FnOnce(&mut Self) + 'static,
+        );
+        #[track_caller]
         fn add_plan(&mut self, time: f64, plan: impl FnOnce(&mut Self) + 'static) {
-            self.add_plan(time, plan);
+            self.add_plan_with_phase(time, ExecutionPhase::Normal, plan);
+        }
+        /// Adds a plan with a label, which the debugger can break on.
+        #[track_caller]
+        fn add_labeled_plan(
+            &mut self,
+            time: f64,
+            label: impl Into<String>,
+            plan: impl FnOnce(&mut Self) + 'static,
+        );
+        /// Like `add_plan_with_phase`, for a plan that `Context::fork` can copy.
+        #[track_caller]
+        fn add_forkable_plan_with_phase(
+            &mut self,
+            time: f64,
+            phase: ExecutionPhase,
+            plan: impl FnOnce(&mut Self) + Clone + 'static,
+        ) {
+            self.add_plan_with_phase(time, phase, plan);
+        }
+        #[track_caller]
+        fn add_forkable_plan(&mut self, time: f64, plan: impl FnOnce(&mut Self) + Clone + 'static) {
+            self.add_forkable_plan_with_phase(time, ExecutionPhase::Normal, plan);
+        }
+
+        /// Handlers are shared with forks of the context, along with any state
+        /// they capture.
+        fn subscribe_to_event<E: Copy + 'static>(
+            &mut self,
+            handler: impl Fn(&mut Self, E) + 'static,
+        );
+        fn emit_event<E: Copy + 'static>(&mut self, event: E);
+    }
+    impl PluginContext for Context {
+        fn try_plugin_data<P: Plugin>(&self) -> Result<&P::DataContainer, ContextError> {
+            self.try_plugin_data::<P>()
+        }
+        fn try_plugin_data_mut<P: Plugin>(
+            &mut self,
+        ) -> Result<&mut P::DataContainer, ContextError> {
+            self.try_plugin_data_mut::<P>()
+        }
+        fn get_current_time(&self) -> f64 {
+            self.get_current_time()
+        }
+        fn add_plan_with_phase(
+            &mut self,
+            time: f64,
+            phase: ExecutionPhase,
+            plan: impl FnOnce(&mut Self) + 'static,
+        ) {
+            self.add_plan_with_phase(time, phase, plan);
+        }
+        fn add_forkable_plan_with_phase(
+            &mut self,
+            time: f64,
+            phase: ExecutionPhase,
+            plan: impl FnOnce(&mut Self) + Clone + 'static,
+        ) {
+            self.add_forkable_plan_with_phase(time, phase, plan);
+        }
+        fn add_labeled_plan(
+            &mut self,
+            time: f64,
+            label: impl Into<String>,
+            plan: impl FnOnce(&mut Self) + 'static,
+        ) {
+            self.add_labeled_plan(time, label, plan);
+        }
+        fn subscribe_to_event<E: Copy + 'static>(
+            &mut self,
+            handler: impl Fn(&mut Self, E) + 'static,
+        ) {
+            self.subscribe_to_event(handler);
+        }
+        fn emit_event<E: Copy + 'static>(&mut self, event: E) {
+            self.emit_event(event);
+        }
+    }
+
+    type TestPlan = Box<dyn FnOnce(&mut TestContext)>;
+    type TestEventHandler<E> = Rc<dyn Fn(&mut TestContext, E)>;
+
+    /// A lightweight `PluginContext` for unit testing extensions.
+    ///
+    /// Plans are recorded instead of executed and time only moves when the test
+    /// says so. As in `Context`, event handlers are queued and run later, by
+    /// `run_callbacks` or `run_next_plan`. Plugin data lives in a regular
+    /// `Context`, so plugin initializers work as usual.
+    pub struct TestContext {
+        context: Context,
+        plans: BTreeMap<PlanKey, TestPlan>,
+        // Labels of pending plans, by plan id
+        plan_labels: HashMap<usize, String>,
+        next_plan_id: usize,
+        current_time: f64,
+        callbacks: VecDeque<TestPlan>,
+        event_handlers: HashMap<TypeId, Box<dyn Any>>,
+        // Each entry is a Vec<E> keyed by the TypeId of E
+        emitted_events: HashMap<TypeId, Box<dyn Any>>,
+    }
+
+    impl Default for TestContext {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl TestContext {
+        pub fn new() -> Self {
+            TestContext {
+                context: Context::new(),
+                plans: BTreeMap::new(),
+                plan_labels: HashMap::new(),
+                next_plan_id: 0,
+                current_time: 0.0,
+                callbacks: VecDeque::new(),
+                event_handlers: HashMap::new(),
+                emitted_events: HashMap::new(),
+            }
+        }
+        /// Seeds the data container for `P`, bypassing its initializer.
+        pub fn set_plugin_data<P: Plugin>(&mut self, data: P::DataContainer) {
+            self.context
+                .data_containers
+                .get_mut()
+                .insert(TypeId::of::<P>(), Box::new(data));
+            self.context
+                .plugin_names
+                .get_mut()
+                .insert(TypeId::of::<P>(), type_name::<P>());
+        }
+        /// Moves time forward without running any plans.
+        pub fn set_current_time(&mut self, time: f64) {
+            assert!(
+                time >= self.current_time,
+                "Time {time} is in the past (current time is {})",
+                self.current_time
+            );
+            self.current_time = time;
+        }
+        /// Returns the (time, phase) of every pending plan in execution order.
+        pub fn planned(&self) -> Vec<(f64, ExecutionPhase)> {
+            self.plans.keys().map(|key| (key.time, key.phase)).collect()
+        }
+        /// Returns the label (if any) of every pending plan in execution order.
+        pub fn planned_labels(&self) -> Vec<Option<&str>> {
+            self.plans
+                .keys()
+                .map(|key| self.plan_labels.get(&key.id).map(String::as_str))
+                .collect()
+        }
+        /// Runs queued callbacks (event handlers), including any they queue.
+        pub fn run_callbacks(&mut self) {
+            while let Some(callback) = self.callbacks.pop_front() {
+                callback(self);
+            }
+        }
+        /// Runs the next pending plan, advancing time to when it was scheduled,
+        /// then the callbacks it queued. Like `Context::execute`, callbacks that
+        /// were already queued run first. Returns false if there was no plan.
+        pub fn run_next_plan(&mut self) -> bool {
+            self.run_callbacks();
+            let Some((key, plan)) = self.plans.pop_first() else {
+                return false;
+            };
+            self.plan_labels.remove(&key.id);
+            self.current_time = key.time;
+            plan(self);
+            self.run_callbacks();
+            true
+        }
+        /// Returns every event of type `E` emitted so far, in order.
+        pub fn emitted_events<E: Copy + 'static>(&self) -> Vec<E> {
+            self.emitted_events
+                .get(&TypeId::of::<E>())
+                .and_then(|events| events.downcast_ref::<Vec<E>>())
+                .cloned()
+                .unwrap_or_default()
+        }
+    }
+
+    impl PluginContext for TestContext {
+        fn try_plugin_data<P: Plugin>(&self) -> Result<&P::DataContainer, ContextError> {
+            self.context.try_plugin_data::<P>()
+        }
+        fn try_plugin_data_mut<P: Plugin>(
+            &mut self,
+        ) -> Result<&mut P::DataContainer, ContextError> {
+            self.context.try_plugin_data_mut::<P>()
+        }
+        fn get_current_time(&self) -> f64 {
+            self.current_time
+        }
+        fn add_plan_with_phase(
+            &mut self,
+            time: f64,
+            phase: ExecutionPhase,
+            plan: impl FnOnce(&mut Self) + 'static,
+        ) {
+            assert!(
+                time >= self.current_time,
+                "Time {time} is in the past (current time is {})",
+                self.current_time
+            );
+            let key = PlanKey {
+                time,
+                phase,
+                id: self.next_plan_id,
+            };
+            self.next_plan_id += 1;
+            self.plans.insert(key, Box::new(plan));
+        }
+        fn add_labeled_plan(
+            &mut self,
+            time: f64,
+            label: impl Into<String>,
+            plan: impl FnOnce(&mut Self) + 'static,
+        ) {
+            let id = self.next_plan_id;
+            self.add_plan_with_phase(time, ExecutionPhase::Normal, plan);
+            self.plan_labels.insert(id, label.into());
+        }
+        fn subscribe_to_event<E: Copy + 'static>(
+            &mut self,
+            handler: impl Fn(&mut Self, E) + 'static,
+        ) {
+            self.event_handlers
+                .entry(TypeId::of::<E>())
+                .or_insert_with(|| Box::<Vec<TestEventHandler<E>>>::default())
+                .downcast_mut::<Vec<TestEventHandler<E>>>()
+                .expect("Failed to downcast event handlers")
+                .push(Rc::new(handler));
+        }
+        fn emit_event<E: Copy + 'static>(&mut self, event: E) {
+            self.emitted_events
+                .entry(TypeId::of::<E>())
+                .or_insert_with(|| Box::<Vec<E>>::default())
+                .downcast_mut::<Vec<E>>()
+                .expect("Failed to downcast emitted events")
+                .push(event);
+            let handlers = self
+                .event_handlers
+                .get(&TypeId::of::<E>())
+                .and_then(|handlers| handlers.downcast_ref::<Vec<TestEventHandler<E>>>())
+                .cloned()
+                .unwrap_or_default();
+            for handler in handlers {
+                self.callbacks
+                    .push_back(Box::new(move |context| handler(context, event)));
+            }
+        }
+    }
+
+    /// Pausing `Context::execute` before plans to inspect the simulation.
+    pub mod debugger {
+        use super::{Context, Plugin};
+        use std::{
+            any::{TypeId, type_name},
+            collections::HashSet,
+            fmt::Debug,
+            io::{self, BufRead, Write},
+        };
+
+        const HELP: &str = "\
+Commands:
+  s, step            run the next plan and pause again
+  c, continue [T]    run until a breakpoint, or until the next plan is after time T
+  b, break LABEL     pause before plans labeled LABEL
+  t, time            print the current time
+  q, queue [N]       print the number of pending plans and the next N (default 5)
+  p, print [PLUGIN]  print a plugin's data, or list the plugins that can be printed
+  quit               stop executing, leaving the remaining plans queued
+  h, help            print this message";
+
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        enum Mode {
+            Step,
+            ContinueUntil(f64),
+            Continue,
+        }
+
+        type Formatter = fn(&Context) -> Option<String>;
+
+        fn format_data<P: Plugin>(context: &Context) -> Option<String>
+        where
+            P::DataContainer: Debug,
+        {
+            // Safety: only reads; see `Context::try_plugin_data`
+            let data_containers = unsafe { &*context.data_containers.get() };
+            data_containers
+                .get(&TypeId::of::<P>())
+                .and_then(|data| data.downcast_ref::<P::DataContainer>())
+                .map(|data| format!("{data:#?}"))
+        }
+
+        /// Reads commands from `input` and writes to `output`; see `HELP`.
+        pub struct Debugger<R, W> {
+            input: R,
+            output: W,
+            mode: Mode,
+            breakpoints: HashSet<String>,
+            formatters: Vec<(&'static str, Formatter)>,
+        }
+
+        impl Debugger<io::StdinLock<'static>, io::Stdout> {
+            /// A debugger driven from the terminal.
+            pub fn stdio() -> Self {
+                Debugger::new(io::stdin().lock(), io::stdout())
+            }
+        }
+
+        impl<R: BufRead, W: Write> Debugger<R, W> {
+            pub fn new(input: R, output: W) -> Self {
+                Debugger {
+                    input,
+                    output,
+                    mode: Mode::Step,
+                    breakpoints: HashSet::new(),
+                    formatters: Vec::new(),
+                }
+            }
+
+            /// Lets `print` show `P`'s data, using its name without the module path.
+            pub fn with_plugin<P: Plugin>(mut self) -> Self
+            where
+                P::DataContainer: Debug,
+            {
+                let name = type_name::<P>().rsplit("::").next().unwrap();
+                self.formatters.push((name, format_data::<P>));
+                self
+            }
+
+            pub fn with_breakpoint(mut self, label: impl Into<String>) -> Self {
+                self.breakpoints.insert(label.into());
+                self
+            }
+
+            pub fn into_output(self) -> W {
+                self.output
+            }
+
+            pub(super) fn should_pause(&self, time: f64, label: Option<&str>) -> bool {
+                let at_breakpoint = label.is_some_and(|label| self.breakpoints.contains(label));
+                at_breakpoint
+                    || match self.mode {
+                        Mode::Step => true,
+                        Mode::ContinueUntil(until) => time > until,
+                        Mode::Continue => false,
+                    }
+            }
+
+            /// Handles commands until one resumes execution. Returns false if
+            /// execution should stop. Running out of input continues execution.
+            pub(super) fn pause(&mut self, context: &Context) -> io::Result<bool> {
+                let next = context.plans.keys().next();
+                if let Some(key) = next {
+                    writeln!(
+                        self.output,
+                        "Paused at time {} before {} at time {}",
+                        context.current_time,
+                        label(context, key.id),
+                        key.time
+                    )?;
+                }
+                loop {
+                    write!(self.output, "> ")?;
+                    self.output.flush()?;
+                    let mut line = String::new();
+                    if self.input.read_line(&mut line)? == 0 {
+                        self.mode = Mode::Continue;
+                        return Ok(true);
+                    }
+                    let mut words = line.split_whitespace();
+                    let command = words.next().unwrap_or("");
+                    let argument = words.collect::<Vec<_>>().join(" ");
+                    match (command, argument.as_str()) {
+                        ("s" | "step", _) => {
+                            self.mode = Mode::Step;
+                            return Ok(true);
+                        }
+                        ("c" | "continue", "") => {
+                            self.mode = Mode::Continue;
+                            return Ok(true);
+                        }
+                        ("c" | "continue", time) => match time.parse::<f64>() {
+                            Ok(time) => {
+                                self.mode = Mode::ContinueUntil(time);
+                                return Ok(true);
+                            }
+                            Err(_) => writeln!(self.output, "Invalid time `{time}`")?,
+                        },
+                        ("b" | "break", "") => writeln!(self.output, "Usage: break LABEL")?,
+                        ("b" | "break", label) => {
+                            self.breakpoints.insert(label.to_string());
+                            writeln!(self.output, "Breakpoint set on `{label}`")?;
+                        }
+                        ("t" | "time", _) => writeln!(self.output, "{}", context.current_time)?,
+                        ("q" | "queue", count) => {
+                            let Ok(count) = (if count.is_empty() {
+                                Ok(5)
+                            } else {
+                                count.parse()
+                            }) else {
+                                writeln!(self.output, "Invalid count `{count}`")?;
+                                continue;
+                            };
+                            writeln!(self.output, "{} pending plans", context.plans.len())?;
+                            for key in context.plans.keys().take(count) {
+                                writeln!(
+                                    self.output,
+                                    "  {}: {}",
+                                    key.time,
+                                    label(context, key.id)
+                                )?;
+                            }
+                        }
+                        ("p" | "print", "") => {
+                            for (name, _) in &self.formatters {
+                                writeln!(self.output, "  {name}")?;
+                            }
+                        }
+                        ("p" | "print", plugin) => {
+                            match self.formatters.iter().find(|(name, _)| *name == plugin) {
+                                Some((_, format)) => match format(context) {
+                                    Some(data) => writeln!(self.output, "{data}")?,
+                                    None => writeln!(self.output, "`{plugin}` is not initialized")?,
+                                },
+                                None => writeln!(self.output, "Unknown plugin `{plugin}`")?,
+                            }
+                        }
+                        ("quit", _) => return Ok(false),
+                        ("h" | "help", _) => writeln!(self.output, "{HELP}")?,
+                        ("", _) => {}
+                        (command, _) => {
+                            writeln!(self.output, "Unknown command `{command}`; try `help`")?
+                        }
+                    }
+                }
+            }
+        }
+
+        /// The plan's label, or where it was added if it has none.
+        fn label(context: &Context, id: usize) -> String {
+            context.plan_info[&id].to_string()
         }
     }
 
@@ -76,14 +980,8 @@ mod context {
 mod bool_plugin {
     use super::*;
 
-    pub struct BoolPlugin;
-    impl Plugin for BoolPlugin {
-        type DataContainer = bool;
-        fn default() -> Self::DataContainer {
-            false
-        }
-    }
-    pub trait BoolExt: PluginContext {
+    #[context_extension(plugin = BoolPlugin, data = bool)]
+    pub trait BoolExt {
         fn set_bool(&mut self, value: bool) {
             let data_container = self.plugin_data_mut::<BoolPlugin>();
             *data_container = value;
@@ -92,30 +990,35 @@ mod bool_plugin {
             *self.plugin_data::<BoolPlugin>()
         }
     }
-    impl<T: PluginContext + ?Sized> BoolExt for T {}
 }
 
 mod number_plugin {
     use super::*;
 
-    pub struct NumberPlugin;
-    impl Plugin for NumberPlugin {
-        type DataContainer = u32;
-        fn default() -> Self::DataContainer {
-            0
-        }
+    #[derive(Debug, Clone, Copy)]
+    pub struct NumberChangeEvent {
+        pub previous: u32,
+        pub current: u32,
     }
 
-    pub trait NumberExt: PluginContext {
+    #[context_extension(plugin = NumberPlugin, data = u32, init = |_context| 0)]
+    pub trait NumberExt {
+        /// Traced plans point at the caller rather than this method.
+        #[track_caller]
         fn schedule_set_number(&mut self, time: f64, value: u32) {
-            self.add_plan(time, move |ctx| {
+            self.add_forkable_plan(time, move |ctx| {
                 ctx.set_number(value);
                 println!("Scheduled number set to: {}", value);
             });
         }
         fn set_number(&mut self, value: u32) {
             let data_container = self.plugin_data_mut::<NumberPlugin>();
+            let previous = *data_container;
             *data_container = value;
+            self.emit_event(NumberChangeEvent {
+                previous,
+                current: value,
+            });
         }
         fn get_number(&self) -> u32 {
             *self.plugin_data::<NumberPlugin>()
@@ -129,22 +1032,31 @@ mod number_plugin {
         let number = context.get_number();
         println!("Number is: {}", number);
     }
-
-    impl NumberExt for Context {}
 }
 
 use bool_plugin::*;
 use context::*;
+use context_extension::context_extension;
 use number_plugin::*;
 
 fn main() {
     let mut context = build_context!();
+    // Plugins are initialized on first access, even through a shared reference
+    assert_eq!(context.get_number(), 0);
     context.set_number(42);
     assert_eq!(context.get_number(), 42);
     context.set_bool(true);
-    assert_eq!(context.get_bool(), true);
+    assert!(context.get_bool());
     assert_eq!(context.get_bool_as_number(), 1);
     do_stuff_with_numbers(&context);
+    context.subscribe_to_event(|ctx, event: NumberChangeEvent| {
+        println!(
+            "Number changed from {} to {} (bool is {})",
+            event.previous,
+            event.current,
+            ctx.get_bool()
+        );
+    });
     context.add_plan(1.0, |ctx| {
         ctx.set_number(100);
         println!("Plan executed, number set to: {}", ctx.get_number());
@@ -152,3 +1064,400 @@ fn main() {
     context.schedule_set_number(1.0, 32);
     context.execute();
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    #[test]
+    fn test_events_are_delivered_through_callbacks() {
+        let mut context = build_context!();
+        let received = Rc::new(RefCell::new(Vec::new()));
+        let received_clone = Rc::clone(&received);
+        context.subscribe_to_event(move |ctx, event: NumberChangeEvent| {
+            received_clone
+                .borrow_mut()
+                .push((event.previous, event.current));
+            // Handlers get exclusive access to the context
+            ctx.set_bool(true);
+        });
+
+        context.set_number(1);
+        // Nothing is delivered until the context runs its callback queue
+        assert!(received.borrow().is_empty());
+        context.schedule_set_number(1.0, 2);
+        context.execute();
+
+        assert_eq!(*received.borrow(), vec![(0, 1), (1, 2)]);
+        assert!(context.get_bool());
+    }
+
+    struct DoubleNumberPlugin;
+    impl Plugin for DoubleNumberPlugin {
+        type DataContainer = u32;
+        fn init(context: &Context) -> Self::DataContainer {
+            context.get_number() * 2
+        }
+    }
+
+    #[test]
+    fn test_init_on_first_read() {
+        let context = build_context!();
+        // Neither plugin has been written to; both are created lazily
+        assert_eq!(*context.plugin_data::<DoubleNumberPlugin>(), 0);
+        assert!(!context.get_bool());
+    }
+
+    #[test]
+    fn test_init_reads_other_plugins() {
+        let mut context = build_context!();
+        context.set_number(21);
+        *context.plugin_data_mut::<DoubleNumberPlugin>() += 1;
+        assert_eq!(*context.plugin_data::<DoubleNumberPlugin>(), 43);
+    }
+
+    #[test]
+    fn test_execute_runs_plans_in_time_order() {
+        let mut context = build_context!();
+        let order = Rc::new(RefCell::new(Vec::new()));
+        for (time, phase) in [
+            (2.0, ExecutionPhase::Normal),
+            (1.0, ExecutionPhase::Last),
+            (1.0, ExecutionPhase::Normal),
+            (1.0, ExecutionPhase::First),
+        ] {
+            let order = Rc::clone(&order);
+            context
+                .add_plan_with_phase(time, phase, move |_| order.borrow_mut().push((time, phase)));
+        }
+        context.execute();
+        assert_eq!(
+            *order.borrow(),
+            vec![
+                (1.0, ExecutionPhase::First),
+                (1.0, ExecutionPhase::Normal),
+                (1.0, ExecutionPhase::Last),
+                (2.0, ExecutionPhase::Normal)
+            ]
+        );
+        assert_eq!(context.get_current_time(), 2.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Time 1 is in the past")]
+    fn test_execute_rejects_past_plans() {
+        let mut context = build_context!();
+        context.add_plan(2.0, |ctx| ctx.schedule_set_number(1.0, 1));
+        context.execute();
+    }
+
+    #[test]
+    fn test_schedule_set_number_with_test_context() {
+        let mut context = TestContext::new();
+        context.set_plugin_data::<NumberPlugin>(7);
+        context.set_current_time(1.0);
+        context.schedule_set_number(3.0, 10);
+        context.schedule_set_number(2.0, 5);
+
+        assert_eq!(
+            context.planned(),
+            vec![(2.0, ExecutionPhase::Normal), (3.0, ExecutionPhase::Normal)]
+        );
+        // Nothing runs until the test asks for it
+        assert_eq!(context.get_number(), 7);
+
+        assert!(context.run_next_plan());
+        assert_eq!(context.get_current_time(), 2.0);
+        assert_eq!(context.get_number(), 5);
+        assert_eq!(context.planned(), vec![(3.0, ExecutionPhase::Normal)]);
+
+        assert!(context.run_next_plan());
+        assert!(!context.run_next_plan());
+        assert_eq!(context.get_number(), 10);
+        let events = context.emitted_events::<NumberChangeEvent>();
+        assert_eq!(
+            events
+                .iter()
+                .map(|event| (event.previous, event.current))
+                .collect::<Vec<_>>(),
+            vec![(7, 5), (5, 10)]
+        );
+    }
+
+    // Like an extension method: only knows about `PluginContext`
+    fn schedule_labeled_increment(context: &mut impl PluginContext, time: f64) {
+        context.add_labeled_plan(time, "increment", |ctx| {
+            ctx.set_number(ctx.get_number() + 1)
+        });
+    }
+
+    #[test]
+    fn test_labeled_plans_from_extensions() {
+        use super::context::debugger::Debugger;
+
+        let mut context = TestContext::new();
+        schedule_labeled_increment(&mut context, 2.0);
+        context.add_plan(1.0, |_| {});
+        assert_eq!(context.planned_labels(), vec![None, Some("increment")]);
+        context.run_next_plan();
+        context.run_next_plan();
+        assert_eq!(context.get_number(), 1);
+        assert!(context.planned_labels().is_empty());
+
+        let mut context = build_context!();
+        context.add_plan(1.0, |_| {});
+        schedule_labeled_increment(&mut context, 2.0);
+        let input = "break increment\ncontinue\nquit\n";
+        let mut debugger = Debugger::new(input.as_bytes(), Vec::new());
+        context.execute_with_debugger(&mut debugger).unwrap();
+        let output = String::from_utf8(debugger.into_output()).unwrap();
+        assert!(output.ends_with("> Paused at time 1 before increment at time 2\n> "));
+        assert_eq!(context.get_number(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Time 1 is in the past")]
+    fn test_test_context_rejects_past_plans() {
+        let mut context = TestContext::new();
+        context.set_current_time(2.0);
+        context.schedule_set_number(1.0, 1);
+    }
+
+    #[test]
+    fn test_test_context_queues_event_handlers() {
+        let mut context = TestContext::new();
+        let received = Rc::new(RefCell::new(Vec::new()));
+        let received_clone = Rc::clone(&received);
+        context.subscribe_to_event(move |ctx: &mut TestContext, event: NumberChangeEvent| {
+            // Handlers see the state after the change, as in `Context`
+            received_clone
+                .borrow_mut()
+                .push((event.current, ctx.get_number()));
+        });
+
+        context.set_number(1);
+        context.set_number(2);
+        assert!(received.borrow().is_empty());
+        context.run_callbacks();
+        assert_eq!(*received.borrow(), vec![(1, 2), (2, 2)]);
+
+        // Handlers for events emitted by a plan run before `run_next_plan` returns
+        context.schedule_set_number(1.0, 3);
+        assert!(context.run_next_plan());
+        assert_eq!(received.borrow().last(), Some(&(3, 3)));
+    }
+
+    struct CyclicPlugin;
+    impl Plugin for CyclicPlugin {
+        type DataContainer = u32;
+        fn init(context: &Context) -> Self::DataContainer {
+            *context.plugin_data::<CyclicPlugin>() + 1
+        }
+    }
+
+    #[test]
+    fn test_try_plugin_data_reports_reentrant_initialization() {
+        struct OuterPlugin;
+        impl Plugin for OuterPlugin {
+            type DataContainer = Result<u32, ContextError>;
+            fn init(context: &Context) -> Self::DataContainer {
+                context.try_plugin_data::<OuterPlugin>().map(|_| 0)
+            }
+        }
+
+        let mut context = build_context!();
+        assert_eq!(
+            *context.plugin_data_mut::<OuterPlugin>(),
+            Err(ContextError::ReentrantInitialization {
+                plugin: std::any::type_name::<OuterPlugin>()
+            })
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "CyclicPlugin` was accessed while it was being initialized")]
+    fn test_plugin_data_panics_on_reentrant_initialization() {
+        let context = build_context!();
+        context.plugin_data::<CyclicPlugin>();
+    }
+
+    #[test]
+    fn test_emit_without_subscribers() {
+        let mut context = build_context!();
+        context.emit_event(NumberChangeEvent {
+            previous: 0,
+            current: 1,
+        });
+        context.execute();
+    }
+
+    #[test]
+    fn test_fork() {
+        let mut context = build_context!();
+        context.register_clone::<NumberPlugin>();
+        context.register_clone::<BoolPlugin>();
+        let events = Rc::new(RefCell::new(0));
+        let events_clone = Rc::clone(&events);
+        context.subscribe_to_event(move |_, _: NumberChangeEvent| {
+            *events_clone.borrow_mut() += 1;
+        });
+        context.set_number(1);
+        context.schedule_set_number(2.0, 5);
+        let forks = Rc::new(RefCell::new(Vec::new()));
+        let forks_clone = Rc::clone(&forks);
+        // Fork mid-run, with the plan at time 2 and the pending event still queued
+        context.add_plan(1.0, move |ctx| {
+            forks_clone.borrow_mut().push(ctx.fork().unwrap())
+        });
+        context.execute();
+        assert_eq!(context.get_number(), 5);
+        assert_eq!(context.get_current_time(), 2.0);
+
+        let mut fork = forks.borrow_mut().pop().unwrap();
+        assert_eq!(fork.get_current_time(), 1.0);
+        assert_eq!(fork.get_number(), 1);
+        // An intervention in the fork doesn't affect the original
+        fork.set_bool(true);
+        fork.schedule_set_number(3.0, 7);
+        fork.execute();
+        assert_eq!(fork.get_number(), 7);
+        assert_eq!(fork.get_current_time(), 3.0);
+        assert!(!context.get_bool());
+        assert_eq!(context.get_number(), 5);
+        // Handlers are shared: two events in the original, two more in the fork
+        assert_eq!(*events.borrow(), 4);
+    }
+
+    #[test]
+    fn test_fork_errors() {
+        let mut context = build_context!();
+        context.set_number(1);
+        assert_eq!(
+            context.fork().err(),
+            Some(ContextError::UnforkableData {
+                plugin: std::any::type_name::<NumberPlugin>()
+            })
+        );
+
+        context.register_clone::<NumberPlugin>();
+        context.add_labeled_plan(1.0, "not forkable", |_| {});
+        assert_eq!(
+            context.fork().err(),
+            Some(ContextError::UnforkablePlan {
+                plan: "not forkable".to_string()
+            })
+        );
+        context.execute();
+        assert!(context.fork().is_ok());
+    }
+
+    #[test]
+    fn test_debugger() {
+        use super::context::debugger::Debugger;
+
+        let mut context = build_context!();
+        context.set_number(1);
+        for time in [1.0, 2.0, 3.0] {
+            context.add_labeled_plan(time, "increment", |ctx| {
+                let number = ctx.get_number();
+                ctx.set_number(number + 1);
+            });
+        }
+        context.add_labeled_plan(1.5, "set bool", |ctx| ctx.set_bool(true));
+
+        let input = "queue 2\nprint NumberPlugin\nprint BoolPlugin\nstep\ntime\n\
+            break increment\ncontinue\nprint\nquit\n";
+        let mut debugger = Debugger::new(input.as_bytes(), Vec::new())
+            .with_plugin::<NumberPlugin>()
+            .with_plugin::<BoolPlugin>();
+        context.execute_with_debugger(&mut debugger).unwrap();
+        let output = String::from_utf8(debugger.into_output()).unwrap();
+        assert_eq!(
+            output,
+            "Paused at time 0 before increment at time 1
+> 4 pending plans
+  1: increment
+  1.5: set bool
+> 1
+> `BoolPlugin` is not initialized
+> Paused at time 1 before set bool at time 1.5
+> 1
+> Breakpoint set on `increment`
+> Paused at time 1.5 before increment at time 2
+>   NumberPlugin
+  BoolPlugin
+> "
+        );
+        // Quitting leaves the remaining plans queued
+        assert_eq!(context.get_number(), 2);
+        assert!(context.get_bool());
+        context.execute();
+        assert_eq!(context.get_number(), 4);
+
+        // Continuing to a time, and running out of input
+        let mut context = build_context!();
+        for time in [1.0, 2.0, 3.0] {
+            context.add_plan(time, |ctx| ctx.set_number(ctx.get_number() + 1));
+        }
+        let mut debugger = Debugger::new("continue 2\ntime\n".as_bytes(), Vec::new());
+        context.execute_with_debugger(&mut debugger).unwrap();
+        let output = String::from_utf8(debugger.into_output()).unwrap();
+        // Unlabeled plans are shown by where they were added
+        let paused = format!("Paused at time 2 before {}:", file!());
+        assert!(output.contains(&paused));
+        assert!(output.ends_with(" at time 3\n> 2\n> "));
+        assert_eq!(context.get_number(), 3);
+    }
+
+    #[test]
+    fn test_trace() {
+        let path = std::env::temp_dir().join(format!(
+            "context-as-trait-{}-trace.jsonl",
+            std::process::id()
+        ));
+        let run = || {
+            let mut context = build_context!();
+            context.trace_to_file(&path).unwrap();
+            context.add_labeled_plan(2.0, "double", |ctx| ctx.set_number(ctx.get_number() * 2));
+            let line = line!() + 1;
+            context.add_plan(1.0, |ctx| ctx.set_number(ctx.get_number() + 1));
+            context.schedule_set_number(3.0, 7);
+            context.execute();
+            let trace = std::fs::read_to_string(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            let records = trace
+                .lines()
+                .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+                .collect::<Vec<_>>();
+            (line, records)
+        };
+
+        let (line, records) = run();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0]["time"], 1.0);
+        assert_eq!(records[0]["label"], serde_json::Value::Null);
+        // `add_plan` records where it was called
+        assert_eq!(
+            records[0]["location"],
+            format!("{}:{line}:21", file!()).as_str()
+        );
+        assert_eq!(records[1]["label"], "double");
+        assert_eq!(records[2]["time"], 3.0);
+        // So does an extension method that is `#[track_caller]`
+        assert_eq!(
+            records[2]["location"],
+            format!("{}:{}:21", file!(), line + 1).as_str()
+        );
+        assert!(records.iter().all(|record| record["duration_ns"].is_u64()));
+
+        // Apart from durations, two runs give the same trace
+        let without_durations = |mut records: Vec<serde_json::Value>| {
+            for record in &mut records {
+                record.as_object_mut().unwrap().remove("duration_ns");
+            }
+            records
+        };
+        assert_eq!(without_durations(records), without_durations(run().1));
+    }
+}
@@ -0,0 +1,280 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    Expr, Ident, ItemTrait, Token, Type, TypeParamBound,
+    parse::{Parse, ParseStream},
+    parse_quote,
+};
+
+/// Turns a trait with default methods into a context extension.
+///
+/// ```rust,ignore
+/// #[context_extension(plugin = NumberPlugin, data = u32, init = |_context| 0)]
+/// pub trait NumberExt {
+///     fn get_number(&self) -> u32 {
+///         *self.plugin_data::<NumberPlugin>()
+///     }
+/// }
+/// ```
+///
+/// expands to `pub trait NumberExt: PluginContext { ... }` plus a blanket
+/// `impl<T: PluginContext + ?Sized> NumberExt for T {}`. `PluginContext`
+/// (and, when a plugin is declared, `Plugin` and `Context`) must be in scope.
+///
+/// The optional `plugin` argument also declares a data plugin with the same
+/// visibility as the trait. `data` is its data container type and `init` an
+/// initializer taking `&Context`; if `init` is omitted the container is
+/// created with `Default::default()`.
+#[proc_macro_attribute]
+pub fn context_extension(args: TokenStream, input: TokenStream) -> TokenStream {
+    expand(args.into(), input.into())
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(args: TokenStream2, input: TokenStream2) -> syn::Result<TokenStream2> {
+    let args: ExtensionArgs = syn::parse2(args)?;
+    let mut item: ItemTrait = syn::parse2(input)?;
+
+    let has_supertrait = item.supertraits.iter().any(|bound| match bound {
+        TypeParamBound::Trait(bound) => bound.path.is_ident("PluginContext"),
+        _ => false,
+    });
+    if !has_supertrait {
+        item.supertraits.push(parse_quote!(PluginContext));
+    }
+
+    let vis = &item.vis;
+    let trait_name = &item.ident;
+    let (_, ty_generics, where_clause) = item.generics.split_for_impl();
+    let mut impl_generics = item.generics.clone();
+    impl_generics
+        .params
+        .push(parse_quote!(__Context: PluginContext + ?Sized));
+    let (impl_generics, _, _) = impl_generics.split_for_impl();
+
+    let plugin = match args.plugin {
+        Some(plugin) => {
+            let Some(data) = args.data else {
+                return Err(syn::Error::new_spanned(
+                    &plugin,
+                    "a plugin requires `data = <type>`",
+                ));
+            };
+            let init = match args.init {
+                Some(init) => quote! {
+                    let init: fn(&Context) -> #data = #init;
+                    init(context)
+                },
+                None => quote! {
+                    let _ = context;
+                    ::std::default::Default::default()
+                },
+            };
+            quote! {
+                #vis struct #plugin;
+                impl Plugin for #plugin {
+                    type DataContainer = #data;
+                    fn init(context: &Context) -> Self::DataContainer {
+                        #init
+                    }
+                }
+            }
+        }
+        None => {
+            if let Some(data) = args.data {
+                return Err(syn::Error::new_spanned(
+                    data,
+                    "`data` requires `plugin = <name>`",
+                ));
+            }
+            if let Some(init) = args.init {
+                return Err(syn::Error::new_spanned(
+                    init,
+                    "`init` requires `plugin = <name>`",
+                ));
+            }
+            quote! {}
+        }
+    };
+
+    Ok(quote! {
+        #plugin
+        #item
+        impl #impl_generics #trait_name #ty_generics for __Context #where_clause {}
+    })
+}
+
+#[derive(Default)]
+struct ExtensionArgs {
+    plugin: Option<Ident>,
+    data: Option<Type>,
+    init: Option<Expr>,
+}
+
+impl Parse for ExtensionArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut args = ExtensionArgs::default();
+        while !input.is_empty() {
+            let key: Ident = input.parse()?;
+            input.parse::<Token![=]>()?;
+            match key.to_string().as_str() {
+                "plugin" if args.plugin.is_none() => args.plugin = Some(input.parse()?),
+                "data" if args.data.is_none() => args.data = Some(input.parse()?),
+                "init" if args.init.is_none() => args.init = Some(input.parse()?),
+                "plugin" | "data" | "init" => {
+                    return Err(syn::Error::new_spanned(key, "duplicate argument"));
+                }
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        key,
+                        "expected one of `plugin`, `data` or `init`",
+                    ));
+                }
+            }
+            if input.is_empty() {
+                break;
+            }
+            input.parse::<Token![,]>()?;
+        }
+        Ok(args)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::expand;
+    use quote::quote;
+    use syn::{File, ItemImpl, ItemTrait, parse_quote};
+
+    fn expand_items(args: proc_macro2::TokenStream, input: proc_macro2::TokenStream) -> File {
+        syn::parse2(expand(args, input).unwrap()).unwrap()
+    }
+
+    fn error(args: proc_macro2::TokenStream) -> String {
+        let input = quote!(
+            trait Ext {}
+        );
+        expand(args, input).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_adds_supertrait_and_blanket_impl() {
+        let file = expand_items(
+            quote!(),
+            quote!(
+                pub trait Ext {
+                    fn f(&self) {}
+                }
+            ),
+        );
+        let [syn::Item::Trait(item), syn::Item::Impl(blanket)] = &file.items[..] else {
+            panic!("unexpected items");
+        };
+        let expected_trait: ItemTrait = parse_quote!(
+            pub trait Ext: PluginContext {
+                fn f(&self) {}
+            }
+        );
+        assert_eq!(*item, expected_trait);
+        let expected_impl: ItemImpl = parse_quote!(
+            impl<__Context: PluginContext + ?Sized> Ext for __Context {}
+        );
+        assert_eq!(*blanket, expected_impl);
+    }
+
+    #[test]
+    fn test_existing_supertraits() {
+        // `PluginContext` isn't added twice, and other supertraits are kept
+        let file = expand_items(
+            quote!(),
+            quote!(
+                trait Ext: PluginContext + Debug {}
+            ),
+        );
+        let expected: ItemTrait = parse_quote!(
+            trait Ext: PluginContext + Debug {}
+        );
+        assert_eq!(file.items[0], syn::Item::Trait(expected));
+
+        let file = expand_items(
+            quote!(),
+            quote!(
+                trait Ext: Debug {}
+            ),
+        );
+        let expected: ItemTrait = parse_quote!(
+            trait Ext: Debug + PluginContext {}
+        );
+        assert_eq!(file.items[0], syn::Item::Trait(expected));
+    }
+
+    #[test]
+    fn test_generic_trait() {
+        let file = expand_items(
+            quote!(),
+            quote!(
+                trait Ext<T: Clone>
+                where
+                    T: Debug,
+                {
+                }
+            ),
+        );
+        // The where clause keeps the trait's trailing comma
+        #[rustfmt::skip]
+        let expected: ItemImpl = parse_quote!(
+            impl<T: Clone, __Context: PluginContext + ?Sized> Ext<T> for __Context where T: Debug, {}
+        );
+        assert_eq!(file.items[1], syn::Item::Impl(expected));
+    }
+
+    #[test]
+    fn test_plugin() {
+        let file = expand_items(
+            quote!(plugin = NumberPlugin, data = u32),
+            quote!(
+                pub(crate) trait NumberExt {}
+            ),
+        );
+        let syn::Item::Struct(plugin) = &file.items[0] else {
+            panic!("expected the plugin struct first");
+        };
+        assert_eq!(plugin.ident, "NumberPlugin");
+        assert_eq!(plugin.vis, parse_quote!(pub(crate)));
+        let syn::Item::Impl(plugin_impl) = &file.items[1] else {
+            panic!("expected the plugin impl second");
+        };
+        let expected: syn::ImplItem = parse_quote!(
+            type DataContainer = u32;
+        );
+        assert_eq!(plugin_impl.items[0], expected);
+        assert_eq!(file.items.len(), 4);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            error(quote!(data = u32)),
+            "`data` requires `plugin = <name>`"
+        );
+        assert_eq!(
+            error(quote!(init = |_context| 0)),
+            "`init` requires `plugin = <name>`"
+        );
+        assert_eq!(
+            error(quote!(plugin = P)),
+            "a plugin requires `data = <type>`"
+        );
+        assert_eq!(
+            error(quote!(plugin = P, plugin = Q, data = u32)),
+            "duplicate argument"
+        );
+        assert_eq!(
+            error(quote!(plugin = P, datum = u32)),
+            "expected one of `plugin`, `data` or `init`"
+        );
+        assert_eq!(error(quote!(plugin P)), "expected `=`");
+    }
+}
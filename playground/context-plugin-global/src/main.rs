@@ -1,73 +1,645 @@
 pub mod context {
-    use polonius_the_crab::prelude::*;
     use std::{
-        any::{Any, TypeId},
-        cell::{OnceCell, RefCell},
-        collections::{HashMap, HashSet},
-        sync::{LazyLock, Mutex},
+        alloc::{GlobalAlloc, Layout, System},
+        any::{Any, TypeId, type_name},
+        cell::{Cell, OnceCell, RefCell, UnsafeCell},
+        collections::HashMap,
+        fmt,
+        sync::{
+            LazyLock, Mutex,
+            atomic::{AtomicBool, Ordering},
+        },
+        time::{Duration, Instant},
     };
 
-    pub static DATA_PLUGINS: LazyLock<Mutex<RefCell<HashSet<TypeId>>>> =
-        LazyLock::new(|| Mutex::new(RefCell::new(HashSet::new())));
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum ContextError {
+        /// The plugin was not in `DATA_PLUGINS` when the context was created and
+        /// has not been accessed since
+        Unregistered {
+            plugin: &'static str,
+            /// A registered plugin with a similar name
+            suggestion: Option<&'static str>,
+        },
+        /// The plugin's data has not been created yet
+        NotInitialized { plugin: &'static str },
+        /// The plugin's initializer (directly or indirectly) accessed the plugin itself
+        ReentrantInitialization { plugin: &'static str },
+        /// A finalizer read its own plugin through the context
+        ReentrantFinalization { plugin: &'static str },
+        /// A finalizer read a plugin that was never initialized
+        InitializedDuringFinalization { plugin: &'static str },
+    }
+
+    impl fmt::Display for ContextError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                ContextError::Unregistered { plugin, suggestion } => {
+                    write!(f, "Data plugin `{plugin}` is not registered")?;
+                    if let Some(suggestion) = suggestion {
+                        write!(f, " (did you mean `{suggestion}`?)")?;
+                    }
+                    Ok(())
+                }
+                ContextError::NotInitialized { plugin } => {
+                    write!(f, "Data plugin `{plugin}` has not been initialized")
+                }
+                ContextError::ReentrantInitialization { plugin } => write!(
+                    f,
+                    "Data plugin `{plugin}` was accessed while it was being initialized"
+                ),
+                ContextError::ReentrantFinalization { plugin } => write!(
+                    f,
+                    "Data plugin `{plugin}` was accessed through the context while it was \
+                     being finalized"
+                ),
+                ContextError::InitializedDuringFinalization { plugin } => write!(
+                    f,
+                    "Data plugin `{plugin}` was accessed during finalization but was never \
+                     initialized"
+                ),
+            }
+        }
+    }
+
+    impl std::error::Error for ContextError {}
+
+    /// Describes a registered data plugin.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct PluginDescriptor {
+        pub type_id: TypeId,
+        /// Fully qualified type name of the plugin
+        pub name: &'static str,
+        /// Module the plugin was defined in
+        pub module_path: &'static str,
+        pub description: Option<&'static str>,
+        /// Type name of the plugin's data container
+        pub data_container: &'static str,
+    }
+
+    impl PluginDescriptor {
+        pub fn of<T: DataPlugin>(
+            module_path: &'static str,
+            description: Option<&'static str>,
+        ) -> Self {
+            PluginDescriptor {
+                type_id: TypeId::of::<T>(),
+                name: type_name::<T>(),
+                module_path,
+                description,
+                data_container: type_name::<T::DataContainer>(),
+            }
+        }
+
+        /// The plugin's type name without its module path
+        pub fn short_name(&self) -> &'static str {
+            short_name(self.name)
+        }
+    }
+
+    fn short_name(name: &str) -> &str {
+        name.rsplit("::").next().unwrap_or(name)
+    }
+
+    pub static DATA_PLUGINS: LazyLock<Mutex<RefCell<HashMap<TypeId, PluginDescriptor>>>> =
+        LazyLock::new(|| Mutex::new(RefCell::new(HashMap::new())));
 
     pub fn add_plugin_to_registry<T: DataPlugin>() {
         DATA_PLUGINS
             .lock()
             .unwrap()
             .borrow_mut()
-            .insert(TypeId::of::<T>());
+            .insert(TypeId::of::<T>(), T::descriptor());
+    }
+
+    // Number of single character edits to turn `a` into `b`
+    fn edit_distance(a: &str, b: &str) -> usize {
+        let b = b.chars().collect::<Vec<_>>();
+        let mut previous = (0..=b.len()).collect::<Vec<_>>();
+        for (i, a_char) in a.chars().enumerate() {
+            let mut current = vec![i + 1];
+            for (j, b_char) in b.iter().enumerate() {
+                let substitution = previous[j] + usize::from(a_char != *b_char);
+                current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+            }
+            previous = current;
+        }
+        previous[b.len()]
+    }
+
+    // Boxed so that a slot stays put when plugins registered on demand grow the map
+    type PluginSlot = Box<OnceCell<Box<dyn Any>>>;
+
+    type Finalizer = fn(&mut dyn Any, &Context);
+
+    fn finalize_data<T: DataPlugin>(data: &mut dyn Any, context: &Context) {
+        T::finalize(data.downcast_mut::<T::DataContainer>().unwrap(), context);
+    }
+
+    /// Allocator that counts the bytes allocated on each thread, so plugin
+    /// initialization profiles can report memory. Install it with
+    /// `#[global_allocator] static ALLOCATOR: CountingAllocator = CountingAllocator;`
+    pub struct CountingAllocator;
+
+    static COUNTING_ALLOCATOR_INSTALLED: AtomicBool = AtomicBool::new(false);
+
+    thread_local! {
+        // Net bytes allocated by this thread
+        static ALLOCATED_BYTES: Cell<isize> = const { Cell::new(0) };
+    }
+
+    fn record_allocation(bytes: isize) {
+        // Only store once: after that the flag's cache line stays shared
+        // between threads instead of bouncing on every allocation
+        if !COUNTING_ALLOCATOR_INSTALLED.load(Ordering::Relaxed) {
+            COUNTING_ALLOCATOR_INSTALLED.store(true, Ordering::Relaxed);
+        }
+        // The thread local is gone while the thread is being torn down
+        let _ = ALLOCATED_BYTES.try_with(|allocated| allocated.set(allocated.get() + bytes));
+    }
+
+    fn allocated_bytes() -> Option<isize> {
+        COUNTING_ALLOCATOR_INSTALLED
+            .load(Ordering::Relaxed)
+            .then(|| ALLOCATED_BYTES.with(Cell::get))
+    }
+
+    // Safety: every call is forwarded to `System` unchanged
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let ptr = unsafe { System.alloc(layout) };
+            if !ptr.is_null() {
+                record_allocation(layout.size() as isize);
+            }
+            ptr
+        }
+
+        unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+            let ptr = unsafe { System.alloc_zeroed(layout) };
+            if !ptr.is_null() {
+                record_allocation(layout.size() as isize);
+            }
+            ptr
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            unsafe { System.dealloc(ptr, layout) };
+            record_allocation(-(layout.size() as isize));
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            let new_ptr = unsafe { System.realloc(ptr, layout, new_size) };
+            if !new_ptr.is_null() {
+                record_allocation(new_size as isize - layout.size() as isize);
+            }
+            new_ptr
+        }
+    }
+
+    /// Time and memory spent in one run of a plugin's initializer.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct PluginInitProfile {
+        pub name: &'static str,
+        /// Including initializers of plugins first accessed by this one
+        pub inclusive_time: Duration,
+        pub exclusive_time: Duration,
+        /// Net bytes allocated, or `None` if `CountingAllocator` is not the
+        /// global allocator. Includes a little of the context's own bookkeeping.
+        pub inclusive_bytes: Option<isize>,
+        pub exclusive_bytes: Option<isize>,
+    }
+
+    #[derive(Default)]
+    struct InitProfiler {
+        // Time and bytes used by nested initializers, one entry per running initializer
+        nested: RefCell<Vec<(Duration, isize)>>,
+        profiles: RefCell<Vec<PluginInitProfile>>,
+    }
+
+    impl InitProfiler {
+        fn profile<T: DataPlugin>(
+            &self,
+            init: impl FnOnce() -> T::DataContainer,
+        ) -> T::DataContainer {
+            self.nested.borrow_mut().push((Duration::ZERO, 0));
+            let bytes_before = allocated_bytes();
+            let start = Instant::now();
+            let data = init();
+            let inclusive_time = start.elapsed();
+            let inclusive_bytes = allocated_bytes()
+                .zip(bytes_before)
+                .map(|(after, before)| after - before);
+
+            let mut nested = self.nested.borrow_mut();
+            let (nested_time, nested_bytes) = nested.pop().unwrap();
+            if let Some((parent_time, parent_bytes)) = nested.last_mut() {
+                *parent_time += inclusive_time;
+                *parent_bytes += inclusive_bytes.unwrap_or(0);
+            }
+            self.profiles.borrow_mut().push(PluginInitProfile {
+                name: type_name::<T>(),
+                inclusive_time,
+                exclusive_time: inclusive_time.saturating_sub(nested_time),
+                inclusive_bytes,
+                exclusive_bytes: inclusive_bytes.map(|bytes| bytes - nested_bytes),
+            });
+            data
+        }
     }
 
     pub struct Context {
-        data_plugins: HashMap<TypeId, OnceCell<Box<dyn Any>>>,
+        // Plugins missing from `DATA_PLUGINS` are added on first access, possibly
+        // through a shared reference
+        data_plugins: UnsafeCell<HashMap<TypeId, PluginSlot>>,
+        plugin_descriptors: RefCell<HashMap<TypeId, PluginDescriptor>>,
+        // Stack of plugins whose initializer is currently running
+        initializing: RefCell<Vec<TypeId>>,
+        // (dependent, dependency) pairs in the order they were first seen
+        dependencies: RefCell<Vec<(TypeId, TypeId)>>,
+        // Plugins in the order their initializers finished
+        initialization_order: RefCell<Vec<TypeId>>,
+        finalizers: RefCell<HashMap<TypeId, Finalizer>>,
+        // The plugin whose finalizer is running; no plugin may be initialized then
+        finalizing: Cell<Option<TypeId>>,
+        finalized: bool,
+        init_profiler: Option<InitProfiler>,
+    }
+
+    impl Default for Context {
+        fn default() -> Self {
+            Self::new()
+        }
     }
 
     impl Context {
         pub fn new() -> Self {
             let mut context = Context {
-                data_plugins: HashMap::new(),
+                data_plugins: UnsafeCell::new(HashMap::new()),
+                plugin_descriptors: RefCell::new(HashMap::new()),
+                initializing: RefCell::new(Vec::new()),
+                dependencies: RefCell::new(Vec::new()),
+                initialization_order: RefCell::new(Vec::new()),
+                finalizers: RefCell::new(HashMap::new()),
+                finalizing: Cell::new(None),
+                finalized: false,
+                init_profiler: None,
             };
             // Register all data plugins from global list
-            for plugin_type_id in DATA_PLUGINS.lock().unwrap().borrow().iter() {
+            for (plugin_type_id, descriptor) in DATA_PLUGINS.lock().unwrap().borrow().iter() {
                 context
                     .data_plugins
-                    .insert(*plugin_type_id, OnceCell::new());
+                    .get_mut()
+                    .insert(*plugin_type_id, Box::new(OnceCell::new()));
+                context
+                    .plugin_descriptors
+                    .get_mut()
+                    .insert(*plugin_type_id, *descriptor);
             }
             context
         }
 
-        pub fn get_data<T: DataPlugin>(&self, _plugin: T) -> &T::DataContainer {
+        /// Returns every plugin registered with this context, sorted by name.
+        pub fn registered_plugins(&self) -> Vec<PluginDescriptor> {
+            let mut plugins = self
+                .plugin_descriptors
+                .borrow()
+                .values()
+                .copied()
+                .collect::<Vec<_>>();
+            plugins.sort_by_key(|descriptor| descriptor.name);
+            plugins
+        }
+
+        /// Returns the plugins initialized so far, in initialization order.
+        pub fn initialized_plugins(&self) -> Vec<PluginDescriptor> {
+            self.initialization_order
+                .borrow()
+                .iter()
+                .map(|type_id| self.plugin_descriptors.borrow()[type_id])
+                .collect()
+        }
+
+        // Returns the plugin's slot, registering the plugin if it was not in
+        // `DATA_PLUGINS` when the context was created
+        fn slot<T: DataPlugin>(&self) -> &OnceCell<Box<dyn Any>> {
             let type_id = TypeId::of::<T>();
-            self.data_plugins
-                .get(&type_id)
-                .expect("Data plugin not registered")
-                .get_or_init(|| Box::new(T::init(self)))
-                .downcast_ref::<T::DataContainer>()
-                .unwrap()
+            // SAFETY: callers may still hold `&OnceCell`s (and data inside them)
+            //  from earlier `slot` calls, e.g. an initializer that read one plugin
+            //  and is now reading a generic one for the first time. Those point
+            //  into `PluginSlot` boxes, which an insert that grows the table moves
+            //  by pointer but never reallocates, and an existing key is never
+            //  replaced. Nothing holds a `&HashMap` across this call, and the
+            //  closure only touches `plugin_descriptors`, so it can't re-enter.
+            let data_plugins = unsafe { &mut *self.data_plugins.get() };
+            data_plugins.entry(type_id).or_insert_with(|| {
+                self.plugin_descriptors
+                    .borrow_mut()
+                    .insert(type_id, T::descriptor());
+                Box::new(OnceCell::new())
+            })
         }
 
-        pub fn get_data_mut<T: DataPlugin>(&mut self, _plugin: T) -> &mut T::DataContainer {
-            let mut self_shadow = self;
+        fn unregistered<T: DataPlugin>(&self) -> ContextError {
+            let plugin = type_name::<T>();
+            // Prefer a plugin with the same name in another module, then the
+            // closest name within a couple of edits
+            let suggestion = self
+                .registered_plugins()
+                .into_iter()
+                .map(|descriptor| {
+                    let distance = edit_distance(
+                        &short_name(plugin).to_lowercase(),
+                        &descriptor.short_name().to_lowercase(),
+                    );
+                    (distance, descriptor.name)
+                })
+                .filter(|(distance, _)| *distance <= 2)
+                .min()
+                .map(|(_, name)| name);
+            ContextError::Unregistered { plugin, suggestion }
+        }
+
+        // Records that the initializer currently running (if any) accessed `T`
+        fn record_access<T: DataPlugin>(&self) {
+            let Some(dependent) = self.initializing.borrow().last().copied() else {
+                return;
+            };
+            let edge = (dependent, TypeId::of::<T>());
+            let mut dependencies = self.dependencies.borrow_mut();
+            if !dependencies.contains(&edge) {
+                dependencies.push(edge);
+            }
+        }
+
+        fn init_data<T: DataPlugin>(&self) -> Result<T::DataContainer, ContextError> {
             let type_id = TypeId::of::<T>();
-            // If the data plugin is already initialized return mutable reference
-            // Use polonius to address borrow checker limitations
-            polonius!(|self_shadow| -> &'polonius mut T::DataContainer {
-                let cell = self_shadow
-                    .data_plugins
-                    .get_mut(&type_id)
-                    .expect("Data plugin not registered");
-                if let Some(any) = cell.get_mut() {
-                    polonius_return!(any.downcast_mut::<T::DataContainer>().unwrap());
-                }
-            });
-            // Initialize the data plugin
-            let data = T::init(self_shadow);
-            let cell = self_shadow.data_plugins.get_mut(&type_id).unwrap();
-            let _ = cell.set(Box::new(data));
-            cell.get_mut()
+            // The plugin being finalized has an empty slot too, but it must not
+            // get a second copy of its data
+            if let Some(finalizing) = self.finalizing.get() {
+                let plugin = type_name::<T>();
+                return Err(if finalizing == type_id {
+                    ContextError::ReentrantFinalization { plugin }
+                } else {
+                    ContextError::InitializedDuringFinalization { plugin }
+                });
+            }
+            if self.initializing.borrow().contains(&type_id) {
+                return Err(ContextError::ReentrantInitialization {
+                    plugin: type_name::<T>(),
+                });
+            }
+            self.initializing.borrow_mut().push(type_id);
+            let data = match &self.init_profiler {
+                Some(profiler) => profiler.profile::<T>(|| T::init(self)),
+                None => T::init(self),
+            };
+            self.initializing.borrow_mut().pop();
+            self.initialization_order.borrow_mut().push(type_id);
+            self.finalizers
+                .borrow_mut()
+                .insert(type_id, finalize_data::<T>);
+            Ok(data)
+        }
+
+        /// Starts recording the time and memory used by each plugin initializer
+        /// that runs from now on; see `init_profile`.
+        pub fn enable_init_profiling(&mut self) {
+            self.init_profiler.get_or_insert_with(InitProfiler::default);
+        }
+
+        /// Returns a profile for every initializer run since profiling was
+        /// enabled, in the order they finished. Empty if profiling is disabled.
+        pub fn init_profile(&self) -> Vec<PluginInitProfile> {
+            self.init_profiler
+                .as_ref()
+                .map(|profiler| profiler.profiles.borrow().clone())
+                .unwrap_or_default()
+        }
+
+        /// Clears the plugin's data so that its initializer runs again on next
+        /// access. The data is dropped without being finalized. Plugins whose
+        /// initializers read it keep their data; see
+        /// `reset_plugin_and_dependents`.
+        pub fn reset_plugin<T: DataPlugin>(&mut self, _plugin: T) {
+            self.reset_plugin_by_id(TypeId::of::<T>());
+        }
+
+        /// Like `reset_plugin`, but also resets every plugin whose initializer
+        /// (directly or transitively) accessed this one.
+        pub fn reset_plugin_and_dependents<T: DataPlugin>(&mut self, _plugin: T) {
+            let mut to_reset = vec![TypeId::of::<T>()];
+            while let Some(type_id) = to_reset.pop() {
+                // Collect dependents before their edges are removed
+                to_reset.extend(
+                    self.dependencies
+                        .get_mut()
+                        .iter()
+                        .filter(|(_, dependency)| *dependency == type_id)
+                        .map(|(dependent, _)| *dependent),
+                );
+                self.reset_plugin_by_id(type_id);
+            }
+        }
+
+        fn reset_plugin_by_id(&mut self, type_id: TypeId) {
+            let Some(cell) = self.data_plugins.get_mut().get_mut(&type_id) else {
+                return;
+            };
+            if cell.take().is_none() {
+                return;
+            }
+            self.initialization_order
+                .get_mut()
+                .retain(|initialized| *initialized != type_id);
+            self.finalizers.get_mut().remove(&type_id);
+            // The initializer records these again when it next runs
+            self.dependencies
+                .get_mut()
+                .retain(|(dependent, _)| *dependent != type_id);
+        }
+
+        /// Hands every initialized plugin its data and a `&Context` through
+        /// `DataPlugin::finalize`, newest first, so the plugins an initializer
+        /// read are still intact when it finishes. Plugins that were reset are
+        /// skipped. Only the first call (or the drop) does anything.
+        pub fn finalize(&mut self) {
+            if self.finalized {
+                return;
+            }
+            self.finalized = true;
+            let initialization_order = self.initialization_order.get_mut().clone();
+            for type_id in initialization_order.iter().rev() {
+                let finalizer = self.finalizers.get_mut()[type_id];
+                // Take the data out so the finalizer can mutate it while reading
+                // other plugins through the context
+                let cell = self.data_plugins.get_mut().get_mut(type_id).unwrap();
+                let Some(mut data) = cell.take() else {
+                    continue;
+                };
+                self.finalizing.set(Some(*type_id));
+                finalizer(data.as_mut(), self);
+                self.finalizing.set(None);
+                // `finalizing` kept the slot empty, so this can't drop the data
+                let cell = self.data_plugins.get_mut().get_mut(type_id).unwrap();
+                let _ = cell.set(data);
+            }
+        }
+
+        /// Returns the dependencies between plugins discovered so far by
+        /// running their initializers.
+        pub fn plugin_dependency_graph(&self) -> PluginDependencyGraph {
+            let name = |type_id: &TypeId| self.plugin_descriptors.borrow()[type_id].name;
+            let initialization_order = self.initialization_order.borrow();
+            PluginDependencyGraph {
+                initialized: initialization_order.iter().map(name).collect(),
+                uninitialized: self
+                    .registered_plugins()
+                    .into_iter()
+                    .filter(|descriptor| !initialization_order.contains(&descriptor.type_id))
+                    .map(|descriptor| descriptor.name)
+                    .collect(),
+                edges: self
+                    .dependencies
+                    .borrow()
+                    .iter()
+                    .map(|(dependent, dependency)| (name(dependent), name(dependency)))
+                    .collect(),
+            }
+        }
+
+        /// Returns the plugin's data without initializing it.
+        pub fn try_get_initialized_data<T: DataPlugin>(
+            &self,
+            _plugin: T,
+        ) -> Result<&T::DataContainer, ContextError> {
+            // Safety: see `Context::slot`
+            let cell = unsafe { (*self.data_plugins.get()).get(&TypeId::of::<T>()) }
+                .ok_or_else(|| self.unregistered::<T>())?;
+            // Only now, since the graph needs the plugin's descriptor
+            self.record_access::<T>();
+            cell.get()
+                .map(|any| any.downcast_ref::<T::DataContainer>().unwrap())
+                .ok_or(ContextError::NotInitialized {
+                    plugin: type_name::<T>(),
+                })
+        }
+
+        pub fn try_get_data<T: DataPlugin>(
+            &self,
+            _plugin: T,
+        ) -> Result<&T::DataContainer, ContextError> {
+            let cell = self.slot::<T>();
+            self.record_access::<T>();
+            // Not get_or_init: a re-entrant initializer should be an error, not a panic
+            if cell.get().is_none() {
+                let data = self.init_data::<T>()?;
+                let _ = cell.set(Box::new(data));
+            }
+            Ok(cell
+                .get()
                 .unwrap()
-                .downcast_mut::<T::DataContainer>()
+                .downcast_ref::<T::DataContainer>()
+                .unwrap())
+        }
+
+        pub fn try_get_data_mut<T: DataPlugin>(
+            &mut self,
+            plugin: T,
+        ) -> Result<&mut T::DataContainer, ContextError> {
+            // `try_get_data` does the initializing and the error reporting, but
+            // its `&self` result can't be upgraded; drop it and find the now
+            // filled slot again through `get_mut`, which needs no unsafe
+            self.try_get_data(plugin)?;
+            Ok(self
+                .data_plugins
+                .get_mut()
+                .get_mut(&TypeId::of::<T>())
+                .and_then(|cell| cell.get_mut())
                 .unwrap()
+                .downcast_mut::<T::DataContainer>()
+                .unwrap())
+        }
+
+        pub fn get_data<T: DataPlugin>(&self, plugin: T) -> &T::DataContainer {
+            self.try_get_data(plugin)
+                .unwrap_or_else(|error| panic!("{error}"))
+        }
+
+        pub fn get_data_mut<T: DataPlugin>(&mut self, plugin: T) -> &mut T::DataContainer {
+            self.try_get_data_mut(plugin)
+                .unwrap_or_else(|error| panic!("{error}"))
+        }
+    }
+
+    impl Drop for Context {
+        fn drop(&mut self) {
+            // Don't risk a double panic while unwinding
+            if !std::thread::panicking() {
+                self.finalize();
+            }
+        }
+    }
+
+    /// Plugin dependencies recorded while initializing plugins, by type name.
+    #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+    pub struct PluginDependencyGraph {
+        /// Plugins in the order their initializers finished
+        pub initialized: Vec<&'static str>,
+        /// Registered plugins that were never initialized
+        pub uninitialized: Vec<&'static str>,
+        /// (dependent, dependency) pairs: the initializer of the first accessed the second
+        #[serde(serialize_with = "serialize_edges")]
+        pub edges: Vec<(&'static str, &'static str)>,
+    }
+
+    // Edges are objects in JSON, so they read the same way round as in DOT
+    fn serialize_edges<S: serde::Serializer>(
+        edges: &[(&'static str, &'static str)],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        #[derive(serde::Serialize)]
+        struct Edge {
+            from: &'static str,
+            to: &'static str,
+        }
+        serializer.collect_seq(edges.iter().map(|&(from, to)| Edge { from, to }))
+    }
+
+    // A DOT string ID; unlike Rust string literals, only `"` and `\` are escaped
+    fn dot_string(value: &str) -> String {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+
+    impl PluginDependencyGraph {
+        /// Renders the graph in Graphviz DOT format. Edges point from a plugin
+        /// to the plugins it depends on; uninitialized plugins are dashed.
+        pub fn to_dot(&self) -> String {
+            let mut dot = String::from("digraph plugins {\n");
+            for (index, plugin) in self.initialized.iter().enumerate() {
+                dot.push_str(&format!(
+                    "    {} [label={}];\n",
+                    dot_string(plugin),
+                    dot_string(&format!("{index}: {plugin}"))
+                ));
+            }
+            for plugin in &self.uninitialized {
+                dot.push_str(&format!("    {} [style=dashed];\n", dot_string(plugin)));
+            }
+            for (dependent, dependency) in &self.edges {
+                dot.push_str(&format!(
+                    "    {} -> {};\n",
+                    dot_string(dependent),
+                    dot_string(dependency)
+                ));
+            }
+            dot.push_str("}\n");
+            dot
+        }
+
+        /// Renders the graph as a JSON object with `initialized`, `uninitialized`
+        /// and `edges` (as `{"from": dependent, "to": dependency}`) fields.
+        pub fn to_json(&self) -> String {
+            serde_json::to_string(self).unwrap()
         }
     }
 
@@ -75,25 +647,215 @@ pub mod context {
         type DataContainer;
 
         fn init(context: &Context) -> Self::DataContainer;
+
+        /// Flushes or summarizes the data at the end of a run. `context` reads
+        /// any other initialized plugin; reading this plugin through it, or a
+        /// plugin that was never initialized, panics.
+        fn finalize(_data: &mut Self::DataContainer, _context: &Context) {}
+
+        fn descriptor() -> PluginDescriptor
+        where
+            Self: Sized,
+        {
+            let name = type_name::<Self>();
+            let module_path = name.rsplit_once("::").map_or("", |(module, _)| module);
+            PluginDescriptor::of::<Self>(module_path, None)
+        }
+    }
+
+    type InitialData = Box<dyn FnOnce(&mut Context)>;
+
+    /// Builds a context with plugins registered explicitly, in addition to
+    /// those in `DATA_PLUGINS`. Plugins are still initialized lazily.
+    #[derive(Default)]
+    pub struct ContextBuilder {
+        plugins: Vec<PluginDescriptor>,
+        initial_data: Vec<InitialData>,
+        init_profiling: bool,
     }
 
+    impl ContextBuilder {
+        pub fn new() -> Self {
+            ContextBuilder::default()
+        }
+
+        pub fn with_plugin<T: DataPlugin>(mut self) -> Self {
+            self.plugins.push(T::descriptor());
+            self
+        }
+
+        /// Profiles plugin initializers; see `Context::init_profile`.
+        pub fn with_init_profiling(mut self) -> Self {
+            self.init_profiling = true;
+            self
+        }
+
+        /// Provides the plugin's data up front instead of running its initializer.
+        pub fn with_data<T: DataPlugin>(mut self, data: T::DataContainer) -> Self {
+            self.initial_data
+                .push(Box::new(move |context: &mut Context| {
+                    context.slot::<T>();
+                    let type_id = TypeId::of::<T>();
+                    let cell = context.data_plugins.get_mut().get_mut(&type_id).unwrap();
+                    if cell.set(Box::new(data)).is_ok() {
+                        context.initialization_order.get_mut().push(type_id);
+                        context
+                            .finalizers
+                            .get_mut()
+                            .insert(type_id, finalize_data::<T>);
+                    }
+                }));
+            self
+        }
+
+        pub fn build(self) -> Context {
+            let mut context = Context::new();
+            if self.init_profiling {
+                context.enable_init_profiling();
+            }
+            for descriptor in self.plugins {
+                context
+                    .data_plugins
+                    .get_mut()
+                    .entry(descriptor.type_id)
+                    .or_insert_with(|| Box::new(OnceCell::new()));
+                context
+                    .plugin_descriptors
+                    .get_mut()
+                    .insert(descriptor.type_id, descriptor);
+            }
+            for initialize in self.initial_data {
+                initialize(&mut context);
+            }
+            context
+        }
+    }
+
+    /// Adds plugins to `DATA_PLUGINS` explicitly, for builds where the `ctor`
+    /// feature is disabled. Call it before creating any contexts.
+    #[macro_export]
+    macro_rules! register_plugins {
+        ($($data_plugin:ty),+ $(,)?) => {
+            $($crate::context::add_plugin_to_registry::<$data_plugin>();)+
+        };
+    }
+
+    #[cfg(feature = "ctor")]
+    #[doc(hidden)]
+    #[macro_export]
+    macro_rules! register_plugin_on_startup {
+        ($data_plugin:ident) => {
+            paste::paste! {
+                #[ctor::ctor]
+                fn [<_register_plugin_$data_plugin:snake>]() {
+                    $crate::context::add_plugin_to_registry::<$data_plugin>()
+                }
+            }
+        };
+    }
+
+    #[cfg(not(feature = "ctor"))]
+    #[doc(hidden)]
+    #[macro_export]
+    macro_rules! register_plugin_on_startup {
+        ($data_plugin:ident) => {};
+    }
+
+    /// Defines a data plugin and registers it with `DATA_PLUGINS`.
+    ///
+    /// ```rust,ignore
+    /// define_data_plugin!(
+    ///     /// Doc comments and attributes are applied to the plugin type
+    ///     #[derive(Debug)]
+    ///     pub CounterPlugin<T: Clone>,
+    ///     Vec<T>,
+    ///     |_context| Vec::new(),
+    ///     "Optional description for the plugin's descriptor"
+    /// );
+    /// ```
+    ///
+    /// The initializer is written as a closure taking `&Context`; a type
+    /// annotation on its argument is optional, but must accept `&Context`
+    /// (`|context: &Context|`). Generic plugins
+    /// are constructed with `CounterPlugin::<u32>::new()`; since their
+    /// monomorphizations aren't known up front, each one is registered by the
+    /// context the first time it is accessed.
     #[macro_export]
     macro_rules! define_data_plugin {
-        ($data_plugin:ident, $data_container:ty, $init:expr) => {
-            struct $data_plugin;
+        (@description) => { None };
+        (@description $description:literal) => { Some($description) };
+        (
+            $(#[$attr:meta])*
+            $vis:vis $data_plugin:ident,
+            $data_container:ty,
+            |$ctx:tt $(: $ctx_ty:ty)?| $body:expr
+            $(, $description:literal)? $(,)?
+        ) => {
+            $(#[$attr])*
+            $vis struct $data_plugin;
 
             impl $crate::context::DataPlugin for $data_plugin {
                 type DataContainer = $data_container;
 
+                // The initializer body is usually a block
+                #[allow(unused_braces)]
                 fn init(context: &$crate::context::Context) -> Self::DataContainer {
-                    $init(context)
+                    // Rebinding checks the closure's type annotation, if any
+                    let $ctx $(: $ctx_ty)? = context;
+                    $body
+                }
+
+                fn descriptor() -> $crate::context::PluginDescriptor {
+                    $crate::context::PluginDescriptor::of::<Self>(
+                        module_path!(),
+                        $crate::define_data_plugin!(@description $($description)?),
+                    )
                 }
             }
 
-            paste::paste! {
-                #[ctor::ctor]
-                fn [<_register_plugin_$data_plugin:snake>]() {
-                    $crate::context::add_plugin_to_registry::<$data_plugin>()
+            $crate::register_plugin_on_startup!($data_plugin);
+        };
+        (
+            $(#[$attr:meta])*
+            $vis:vis $data_plugin:ident<$($param:ident $(: $bound:path)?),+ $(,)?>,
+            $data_container:ty,
+            |$ctx:tt $(: $ctx_ty:ty)?| $body:expr
+            $(, $description:literal)? $(,)?
+        ) => {
+            $(#[$attr])*
+            $vis struct $data_plugin<$($param),+>(::std::marker::PhantomData<fn() -> ($($param,)+)>);
+
+            impl<$($param),+> $data_plugin<$($param),+> {
+                #[allow(dead_code)]
+                $vis const fn new() -> Self {
+                    $data_plugin(::std::marker::PhantomData)
+                }
+            }
+
+            impl<$($param),+> ::std::default::Default for $data_plugin<$($param),+> {
+                fn default() -> Self {
+                    Self::new()
+                }
+            }
+
+            impl<$($param: 'static $(+ $bound)?),+> $crate::context::DataPlugin
+                for $data_plugin<$($param),+>
+            {
+                type DataContainer = $data_container;
+
+                // The initializer body is usually a block
+                #[allow(unused_braces)]
+                fn init(context: &$crate::context::Context) -> Self::DataContainer {
+                    // Rebinding checks the closure's type annotation, if any
+                    let $ctx $(: $ctx_ty)? = context;
+                    $body
+                }
+
+                fn descriptor() -> $crate::context::PluginDescriptor {
+                    $crate::context::PluginDescriptor::of::<Self>(
+                        module_path!(),
+                        $crate::define_data_plugin!(@description $($description)?),
+                    )
                 }
             }
         };
@@ -104,7 +866,7 @@ pub mod bool_plugin {
     use super::context::*;
     use super::define_data_plugin;
 
-    define_data_plugin!(BoolPlugin, bool, |_context| false);
+    define_data_plugin!(BoolPlugin, bool, |_context| false, "Stores a single flag");
 
     pub trait BoolExt {
         fn set_bool(&mut self, value: bool);
@@ -146,14 +908,498 @@ mod number_plugin {
     }
 }
 
+pub mod parameters {
+    use super::context::{Context, ContextBuilder};
+    use super::define_data_plugin;
+    use serde::de::DeserializeOwned;
+    use std::{fmt, fs, path::Path};
+
+    /// A typed view of one top-level key of the parameters file.
+    pub trait Parameter: DeserializeOwned + 'static {
+        const KEY: &'static str;
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum ParameterError {
+        /// The parameters file could not be read
+        Io { path: String, message: String },
+        /// The parameters file is not valid JSON or TOML
+        Parse { path: String, message: String },
+        /// The parameters file has an extension other than `.json` or `.toml`
+        UnsupportedFormat { path: String },
+        /// No value was provided for the key
+        Missing { key: &'static str },
+        /// The value at `key` (a dotted path) does not match the parameter type
+        Invalid { key: String, message: String },
+    }
+
+    impl fmt::Display for ParameterError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                ParameterError::Io { path, message } => {
+                    write!(f, "Failed to read parameters file `{path}`: {message}")
+                }
+                ParameterError::Parse { path, message } => {
+                    write!(f, "Failed to parse parameters file `{path}`: {message}")
+                }
+                ParameterError::UnsupportedFormat { path } => write!(
+                    f,
+                    "Parameters file `{path}` must have a .json or .toml extension"
+                ),
+                ParameterError::Missing { key } => write!(f, "Missing parameter `{key}`"),
+                ParameterError::Invalid { key, message } => {
+                    write!(f, "Invalid parameter `{key}`: {message}")
+                }
+            }
+        }
+    }
+
+    impl std::error::Error for ParameterError {}
+
+    /// Parameter values by top-level key, as loaded from the parameters file.
+    #[derive(Debug, Clone, Default)]
+    pub struct Parameters {
+        values: serde_json::Map<String, serde_json::Value>,
+    }
+
+    impl Parameters {
+        pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ParameterError> {
+            let path = path.as_ref();
+            let display = path.display().to_string();
+            let contents = fs::read_to_string(path).map_err(|error| ParameterError::Io {
+                path: display.clone(),
+                message: error.to_string(),
+            })?;
+            let parse_error = |message: String| ParameterError::Parse {
+                path: display.clone(),
+                message,
+            };
+            let value = match path.extension().and_then(|extension| extension.to_str()) {
+                Some("json") => serde_json::from_str::<serde_json::Value>(&contents)
+                    .map_err(|error| parse_error(error.to_string()))?,
+                Some("toml") => toml::from_str::<serde_json::Value>(&contents)
+                    .map_err(|error| parse_error(error.to_string()))?,
+                _ => return Err(ParameterError::UnsupportedFormat { path: display }),
+            };
+            match value {
+                serde_json::Value::Object(values) => Ok(Parameters { values }),
+                _ => Err(parse_error("expected a table of parameters".to_string())),
+            }
+        }
+
+        /// Deserializes the value at `P::KEY`. Values aren't cached, so code that
+        /// reads a parameter often should keep it (e.g. in its plugin's data).
+        pub fn get<P: Parameter>(&self) -> Result<P, ParameterError> {
+            let value = self
+                .values
+                .get(P::KEY)
+                .ok_or(ParameterError::Missing { key: P::KEY })?;
+            serde_path_to_error::deserialize(value).map_err(|error| {
+                let path = error.path().to_string();
+                ParameterError::Invalid {
+                    key: if path == "." {
+                        P::KEY.to_string()
+                    } else {
+                        format!("{}.{path}", P::KEY)
+                    },
+                    message: error.into_inner().to_string(),
+                }
+            })
+        }
+    }
+
+    define_data_plugin!(
+        ParametersPlugin,
+        Parameters,
+        |_context| Parameters::default(),
+        "Parameters loaded from a JSON or TOML file"
+    );
+
+    impl ContextBuilder {
+        /// Loads parameters for the context from a `.json` or `.toml` file.
+        pub fn with_parameters_file(self, path: impl AsRef<Path>) -> Result<Self, ParameterError> {
+            Ok(self.with_data::<ParametersPlugin>(Parameters::from_file(path)?))
+        }
+    }
+
+    /// Each call deserializes the parameter again; see `Parameters::get`.
+    pub trait ParametersExt {
+        fn try_get_param<P: Parameter>(&self) -> Result<P, ParameterError>;
+        fn get_param<P: Parameter>(&self) -> P;
+    }
+
+    impl ParametersExt for Context {
+        fn try_get_param<P: Parameter>(&self) -> Result<P, ParameterError> {
+            self.get_data(ParametersPlugin).get::<P>()
+        }
+        fn get_param<P: Parameter>(&self) -> P {
+            self.try_get_param::<P>()
+                .unwrap_or_else(|error| panic!("{error}"))
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+        use serde::Deserialize;
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Infection {
+            r0: f64,
+            infectious_period: u32,
+        }
+        impl Parameter for Infection {
+            const KEY: &'static str = "infection";
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Population(usize);
+        impl Parameter for Population {
+            const KEY: &'static str = "population";
+        }
+
+        define_data_plugin!(InfectiousDays, u32, |context: &Context| {
+            context.get_param::<Infection>().infectious_period * 24
+        });
+
+        fn write_parameters(name: &str, contents: &str) -> std::path::PathBuf {
+            let path = std::env::temp_dir().join(format!(
+                "context-plugin-global-{}-{name}",
+                std::process::id()
+            ));
+            fs::write(&path, contents).unwrap();
+            path
+        }
+
+        #[test]
+        fn test_json_parameters() {
+            let path = write_parameters(
+                "params.json",
+                r#"{"infection": {"r0": 1.5, "infectious_period": 5}, "population": 1000}"#,
+            );
+            let context = ContextBuilder::new()
+                .with_parameters_file(&path)
+                .unwrap()
+                .build();
+            fs::remove_file(&path).unwrap();
+            assert_eq!(
+                context.get_param::<Infection>(),
+                Infection {
+                    r0: 1.5,
+                    infectious_period: 5
+                }
+            );
+            assert_eq!(context.get_param::<Population>(), Population(1000));
+            // Plugin initializers can read parameters
+            assert_eq!(*context.get_data(InfectiousDays), 120);
+        }
+
+        #[test]
+        fn test_toml_parameters() {
+            let path = write_parameters(
+                "params.toml",
+                "population = 10\n\n[infection]\nr0 = 2.0\ninfectious_period = 3\n",
+            );
+            let context = ContextBuilder::new()
+                .with_parameters_file(&path)
+                .unwrap()
+                .build();
+            fs::remove_file(&path).unwrap();
+            assert_eq!(context.get_param::<Infection>().r0, 2.0);
+            assert_eq!(context.get_param::<Population>(), Population(10));
+        }
+
+        #[test]
+        fn test_invalid_parameters_name_the_key() {
+            let path = write_parameters(
+                "invalid.json",
+                r#"{"infection": {"r0": "high", "infectious_period": 5}}"#,
+            );
+            let context = ContextBuilder::new()
+                .with_parameters_file(&path)
+                .unwrap()
+                .build();
+            fs::remove_file(&path).unwrap();
+            let error = context.try_get_param::<Infection>().unwrap_err();
+            assert!(matches!(
+                &error,
+                ParameterError::Invalid { key, .. } if key == "infection.r0"
+            ));
+            assert_eq!(
+                context.try_get_param::<Population>(),
+                Err(ParameterError::Missing { key: "population" })
+            );
+        }
+
+        #[test]
+        fn test_bad_files() {
+            let path = write_parameters("params.yaml", "population: 10");
+            assert_eq!(
+                ContextBuilder::new()
+                    .with_parameters_file(&path)
+                    .err()
+                    .unwrap(),
+                ParameterError::UnsupportedFormat {
+                    path: path.display().to_string()
+                }
+            );
+            fs::remove_file(&path).unwrap();
+            let path = write_parameters("broken.json", "{");
+            assert!(matches!(
+                ContextBuilder::new().with_parameters_file(&path).err(),
+                Some(ParameterError::Parse { .. })
+            ));
+            fs::remove_file(&path).unwrap();
+        }
+
+        #[test]
+        fn test_no_parameters_file() {
+            let context = Context::new();
+            assert_eq!(
+                context.try_get_param::<Population>(),
+                Err(ParameterError::Missing { key: "population" })
+            );
+        }
+    }
+}
+
+pub mod replicates {
+    use super::context::{Context, ContextBuilder};
+    use super::define_data_plugin;
+    use std::{
+        num::NonZeroUsize,
+        sync::{
+            Mutex,
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+        },
+        thread,
+    };
+
+    // Tells the other workers to stop if a replicate panics on this thread
+    struct StopOnPanic<'a>(&'a AtomicBool);
+
+    impl Drop for StopOnPanic<'_> {
+        fn drop(&mut self) {
+            if thread::panicking() {
+                self.0.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Identifies one replicate of a `run_replicates` batch.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Replicate {
+        pub index: usize,
+        /// Derived from the batch's seed base and `index` only, so it doesn't
+        /// depend on which worker runs the replicate
+        pub seed: u64,
+    }
+
+    impl Replicate {
+        /// The `index`-th output of SplitMix64 started from `seed_base`.
+        pub fn new(seed_base: u64, index: usize) -> Self {
+            let mut z =
+                seed_base.wrapping_add((index as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            Replicate {
+                index,
+                seed: z ^ (z >> 31),
+            }
+        }
+    }
+
+    define_data_plugin!(
+        pub ReplicatePlugin,
+        Option<Replicate>,
+        |_context| None,
+        "The replicate a context was built for by `run_replicates`"
+    );
+
+    type MakeBuilder<'a> = Box<dyn Fn() -> ContextBuilder + Sync + 'a>;
+
+    /// Runs independent simulations, each in a fresh context, on a pool of
+    /// worker threads.
+    pub struct ReplicateRunner<'a> {
+        count: usize,
+        seed_base: u64,
+        threads: Option<NonZeroUsize>,
+        make_builder: MakeBuilder<'a>,
+    }
+
+    impl<'a> ReplicateRunner<'a> {
+        pub fn new(count: usize, seed_base: u64) -> Self {
+            ReplicateRunner {
+                count,
+                seed_base,
+                threads: None,
+                make_builder: Box::new(ContextBuilder::new),
+            }
+        }
+
+        /// Number of worker threads; defaults to the available parallelism.
+        pub fn threads(mut self, threads: NonZeroUsize) -> Self {
+            self.threads = Some(threads);
+            self
+        }
+
+        /// Starts every replicate's context from the returned builder, e.g. to
+        /// load parameters.
+        pub fn context_builder(
+            mut self,
+            make_builder: impl Fn() -> ContextBuilder + Sync + 'a,
+        ) -> Self {
+            self.make_builder = Box::new(make_builder);
+            self
+        }
+
+        /// Runs `run` once per replicate and returns the outputs by replicate
+        /// index. If a replicate panics, no more replicates are started and the
+        /// panic is propagated once the running ones finish.
+        pub fn run<R: Send>(self, run: impl Fn(&mut Context, Replicate) -> R + Sync) -> Vec<R> {
+            let threads = self
+                .threads
+                .or_else(|| thread::available_parallelism().ok())
+                .map_or(1, NonZeroUsize::get)
+                .min(self.count.max(1));
+            let next_index = AtomicUsize::new(0);
+            let stopped = AtomicBool::new(false);
+            let outputs = Mutex::new((0..self.count).map(|_| None).collect::<Vec<_>>());
+            thread::scope(|scope| {
+                for _ in 0..threads {
+                    scope.spawn(|| {
+                        let _stop_on_panic = StopOnPanic(&stopped);
+                        loop {
+                            if stopped.load(Ordering::Relaxed) {
+                                break;
+                            }
+                            let index = next_index.fetch_add(1, Ordering::Relaxed);
+                            if index >= self.count {
+                                break;
+                            }
+                            let replicate = Replicate::new(self.seed_base, index);
+                            let mut context = (self.make_builder)()
+                                .with_data::<ReplicatePlugin>(Some(replicate))
+                                .build();
+                            let output = run(&mut context, replicate);
+                            // Finalize before reporting the replicate as done
+                            drop(context);
+                            outputs.lock().unwrap()[index] = Some(output);
+                        }
+                    });
+                }
+            });
+            outputs
+                .into_inner()
+                .unwrap()
+                .into_iter()
+                .map(Option::unwrap)
+                .collect()
+        }
+    }
+
+    /// Runs `count` replicates on all available cores; see `ReplicateRunner`.
+    pub fn run_replicates<R: Send>(
+        count: usize,
+        seed_base: u64,
+        run: impl Fn(&mut Context, Replicate) -> R + Sync,
+    ) -> Vec<R> {
+        ReplicateRunner::new(count, seed_base).run(run)
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        define_data_plugin!(Draws, Vec<u64>, |context: &Context| {
+            // Stands in for a random number generator seeded from the replicate
+            let seed = context.get_data(ReplicatePlugin).unwrap().seed;
+            (0..3).map(|i| seed.rotate_left(i * 8)).collect()
+        });
+
+        define_data_plugin!(Offset, u64, |_context| 0);
+
+        fn simulate(context: &mut Context, replicate: Replicate) -> (usize, u64) {
+            context.get_data_mut(Draws).push(replicate.index as u64);
+            let offset = *context.get_data(Offset);
+            (
+                replicate.index,
+                context.get_data(Draws).iter().fold(offset, |a, b| a ^ b),
+            )
+        }
+
+        #[test]
+        fn test_results_independent_of_thread_count() {
+            let single = ReplicateRunner::new(50, 7)
+                .threads(NonZeroUsize::new(1).unwrap())
+                .run(simulate);
+            let many = ReplicateRunner::new(50, 7)
+                .threads(NonZeroUsize::new(8).unwrap())
+                .run(simulate);
+            assert_eq!(single, many);
+            assert_eq!(run_replicates(50, 7, simulate), single);
+            assert!(single.iter().enumerate().all(|(i, (index, _))| i == *index));
+
+            // Different seed bases give different replicates
+            assert_ne!(run_replicates(50, 8, simulate), single);
+        }
+
+        #[test]
+        fn test_replicate_seeds() {
+            let seeds = run_replicates(100, 0, |context, replicate| {
+                assert_eq!(*context.get_data(ReplicatePlugin), Some(replicate));
+                replicate.seed
+            });
+            let mut unique = seeds.clone();
+            unique.sort();
+            unique.dedup();
+            assert_eq!(unique.len(), 100);
+            assert_eq!(seeds[3], Replicate::new(0, 3).seed);
+        }
+
+        #[test]
+        fn test_context_builder() {
+            let outputs = ReplicateRunner::new(4, 1)
+                .context_builder(|| ContextBuilder::new().with_data::<Offset>(1))
+                .run(|context, _| *context.get_data(Offset));
+            assert_eq!(outputs, vec![1; 4]);
+            assert!(run_replicates(0, 1, simulate).is_empty());
+        }
+
+        #[test]
+        fn test_panic_stops_remaining_replicates() {
+            // With one worker, replicates start in index order, so none after
+            // the panicking one may start
+            let started = Mutex::new(Vec::new());
+            let result = std::panic::catch_unwind(|| {
+                ReplicateRunner::new(1000, 1)
+                    .threads(NonZeroUsize::new(1).unwrap())
+                    .run(|_, replicate| {
+                        started.lock().unwrap().push(replicate.index);
+                        if replicate.index == 2 {
+                            panic!("replicate 2 failed");
+                        }
+                    })
+            });
+            assert!(result.is_err());
+            assert_eq!(started.into_inner().unwrap(), vec![0, 1, 2]);
+        }
+    }
+}
+
 use bool_plugin::BoolExt;
 use context::*;
 
 use crate::number_plugin::NumberExt;
 
+// Lets initialization profiles report memory
+#[cfg(feature = "count-allocations")]
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
 pub fn main() {
     // Example usage
-    let mut context = Context::new();
+    let mut context = ContextBuilder::new().with_init_profiling().build();
     assert!(!context.get_bool());
     assert_eq!(context.get_bool_as_number(), 0);
     assert_eq!(context.get_number(), 0);
@@ -162,12 +1408,35 @@ pub fn main() {
     assert_eq!(context.get_bool_as_number(), 1);
     context.set_number(2);
     assert_eq!(context.get_number(), 2);
+    for plugin in context.registered_plugins() {
+        println!(
+            "{} ({}): {}",
+            plugin.name,
+            plugin.data_container,
+            plugin.description.unwrap_or("no description")
+        );
+    }
+    print!("{}", context.plugin_dependency_graph().to_dot());
+    for profile in context.init_profile() {
+        println!(
+            "{}: {:?} ({:?} exclusive), {:?} bytes",
+            profile.name, profile.inclusive_time, profile.exclusive_time, profile.inclusive_bytes
+        );
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::context::Context;
-    use super::define_data_plugin;
+    use super::context::{Context, ContextBuilder, ContextError, DataPlugin};
+    use super::{define_data_plugin, register_plugins};
+
+    // Tests that depend on what is in the registry before any plugin is accessed
+    // register explicitly when plugins are not registered before main
+    fn new_context() -> Context {
+        #[cfg(not(feature = "ctor"))]
+        register_plugins!(A, B, C, Cyclic, TryCyclic, Described);
+        Context::new()
+    }
 
     define_data_plugin!(A, usize, |context: &Context| {
         println!("Initializing A");
@@ -193,6 +1462,28 @@ mod test {
         assert_eq!(*context.get_data(B), 1);
     }
 
+    #[test]
+    fn test_mut_initializes_dependencies() {
+        let mut context = Context::new();
+
+        // A's initializer reads B, which reads C, none of them initialized yet
+        *context.get_data_mut(A) += 10;
+        assert_eq!(
+            context
+                .initialized_plugins()
+                .iter()
+                .map(|descriptor| descriptor.short_name())
+                .collect::<Vec<_>>(),
+            vec!["C", "B", "A"]
+        );
+        assert_eq!(*context.get_data(A), 12);
+
+        // Already initialized dependencies are not initialized again
+        *context.get_data_mut(C) += 1;
+        assert_eq!(*context.get_data_mut(B), 1);
+        assert_eq!(context.initialized_plugins().len(), 3);
+    }
+
     #[test]
     fn test_mut() {
         let mut context = Context::new();
@@ -201,4 +1492,500 @@ mod test {
         assert_eq!(*context.get_data(A), 3);
         assert_eq!(*context.get_data(B), 2);
     }
+
+    define_data_plugin!(
+        /// Counts values of any type
+        #[derive(Debug)]
+        pub CounterPlugin<T: Clone>,
+        Vec<T>,
+        |_context| Vec::new(),
+        "Values seen so far"
+    );
+
+    define_data_plugin!(
+        /// A public plugin whose initializer depends on a generic one
+        pub(crate) CountedNumbers,
+        usize,
+        |context| context.get_data(CounterPlugin::<u32>::new()).len()
+    );
+
+    #[test]
+    fn test_generic_plugin() {
+        let mut context = Context::new();
+        context.get_data_mut(CounterPlugin::<u32>::new()).push(1);
+        context.get_data_mut(CounterPlugin::<u32>::new()).push(2);
+        context
+            .get_data_mut(CounterPlugin::<&str>::new())
+            .push("one");
+
+        assert_eq!(*context.get_data(CounterPlugin::<u32>::new()), vec![1, 2]);
+        assert_eq!(*context.get_data(CounterPlugin::<&str>::new()), vec!["one"]);
+        assert_eq!(*context.get_data(CountedNumbers), 2);
+
+        // Each monomorphization is registered separately
+        let descriptors = context
+            .registered_plugins()
+            .into_iter()
+            .filter(|descriptor| descriptor.name.contains("CounterPlugin<"))
+            .collect::<Vec<_>>();
+        assert_eq!(descriptors.len(), 2);
+        for descriptor in descriptors {
+            assert_eq!(descriptor.module_path, "context_plugin_global::test");
+            assert_eq!(descriptor.description, Some("Values seen so far"));
+        }
+        assert_eq!(
+            format!("{:?}", CounterPlugin::<u32>::new()),
+            "CounterPlugin(PhantomData<fn() -> (u32,)>)"
+        );
+    }
+
+    #[test]
+    fn test_builder() {
+        let mut context = ContextBuilder::new()
+            .with_plugin::<A>()
+            .with_plugin::<B>()
+            .with_plugin::<C>()
+            .build();
+        // Registering doesn't initialize anything
+        assert!(context.initialized_plugins().is_empty());
+
+        *context.get_data_mut(C) = 1;
+        assert_eq!(*context.get_data(A), 3);
+        assert_eq!(*context.get_data(B), 2);
+    }
+
+    #[test]
+    fn test_register_plugins() {
+        register_plugins!(A, B, C);
+        let context = Context::new();
+        let registered = context
+            .registered_plugins()
+            .iter()
+            .map(|descriptor| descriptor.name)
+            .collect::<Vec<_>>();
+        for name in [
+            std::any::type_name::<A>(),
+            std::any::type_name::<B>(),
+            std::any::type_name::<C>(),
+        ] {
+            assert!(registered.contains(&name));
+        }
+        assert!(context.initialized_plugins().is_empty());
+
+        assert_eq!(*context.get_data(C), 0);
+        assert_eq!(*context.get_data(A), 2);
+        assert_eq!(*context.get_data(B), 1);
+    }
+
+    define_data_plugin!(Described, (), |_| (), "A plugin with a description");
+
+    // Deliberately not registered with `define_data_plugin!`
+    struct Unregistered;
+    impl DataPlugin for Unregistered {
+        type DataContainer = usize;
+        fn init(_context: &Context) -> Self::DataContainer {
+            0
+        }
+    }
+
+    // A typo of `Cyclic`, deliberately not registered
+    struct Cyclik;
+    impl DataPlugin for Cyclik {
+        type DataContainer = usize;
+        fn init(_context: &Context) -> Self::DataContainer {
+            0
+        }
+    }
+
+    define_data_plugin!(Cyclic, usize, |context: &Context| {
+        *context.get_data(Cyclic) + 1
+    });
+
+    define_data_plugin!(
+        TryCyclic,
+        Result<usize, ContextError>,
+        |context: &Context| { context.try_get_data(TryCyclic).map(|_| 0) }
+    );
+
+    #[test]
+    fn test_dependency_graph() {
+        let context = new_context();
+        context.get_data(A);
+        context.get_data(B);
+
+        let graph = context.plugin_dependency_graph();
+        let [a, b, c] = [
+            std::any::type_name::<A>(),
+            std::any::type_name::<B>(),
+            std::any::type_name::<C>(),
+        ];
+        assert_eq!(graph.initialized, vec![c, b, a]);
+        assert_eq!(graph.edges, vec![(a, b), (b, c)]);
+        assert!(
+            graph
+                .uninitialized
+                .contains(&std::any::type_name::<Cyclic>())
+        );
+        assert!(!graph.uninitialized.contains(&a));
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph plugins {"));
+        assert!(dot.contains(&format!("\"{a}\" -> \"{b}\";")));
+        assert!(dot.contains(&format!(
+            "\"{}\" [style=dashed];",
+            std::any::type_name::<Cyclic>()
+        )));
+
+        let json = serde_json::from_str::<serde_json::Value>(&graph.to_json()).unwrap();
+        assert_eq!(json["initialized"], serde_json::json!([c, b, a]));
+        assert_eq!(json["edges"][1], serde_json::json!({"from": b, "to": c}));
+    }
+
+    #[test]
+    fn test_dot_escapes_only_quotes_and_backslashes() {
+        let graph = crate::PluginDependencyGraph {
+            initialized: vec![r#"Say<"hé">"#, r"a\b"],
+            uninitialized: Vec::new(),
+            edges: vec![(r#"Say<"hé">"#, r"a\b")],
+        };
+        assert_eq!(
+            graph.to_dot(),
+            r#"digraph plugins {
+    "Say<\"hé\">" [label="0: Say<\"hé\">"];
+    "a\\b" [label="1: a\\b"];
+    "Say<\"hé\">" -> "a\\b";
+}
+"#
+        );
+    }
+
+    struct PeeksAtUnregistered;
+    impl DataPlugin for PeeksAtUnregistered {
+        type DataContainer = bool;
+        fn init(context: &Context) -> Self::DataContainer {
+            context.try_get_initialized_data(Unregistered).is_ok()
+        }
+    }
+
+    #[test]
+    fn test_dependency_graph_ignores_failed_peeks() {
+        let context = Context::new();
+        assert!(!*context.get_data(PeeksAtUnregistered));
+        let graph = context.plugin_dependency_graph();
+        assert!(graph.edges.is_empty());
+
+        // Once the plugin has a slot, peeking at it is a dependency
+        let context = Context::new();
+        context.get_data(Unregistered);
+        assert!(*context.get_data(PeeksAtUnregistered));
+        let edge = (
+            std::any::type_name::<PeeksAtUnregistered>(),
+            std::any::type_name::<Unregistered>(),
+        );
+        assert_eq!(context.plugin_dependency_graph().edges, vec![edge]);
+    }
+
+    // Not registered either; its initializer depends on another unregistered plugin
+    struct DependsOnUnregistered;
+    impl DataPlugin for DependsOnUnregistered {
+        type DataContainer = usize;
+        fn init(context: &Context) -> Self::DataContainer {
+            *context.get_data(Unregistered) + *context.get_data(C) + 1
+        }
+    }
+
+    #[test]
+    fn test_reset_plugin() {
+        let mut context = Context::new();
+        assert_eq!(*context.get_data(A), 2);
+        *context.get_data_mut(C) = 5;
+
+        // Only C is reset, so A and B keep values computed from the old C
+        context.reset_plugin(C);
+        assert!(context.try_get_initialized_data(C).is_err());
+        assert_eq!(*context.get_data(A), 2);
+        assert_eq!(*context.get_data(C), 0);
+    }
+
+    #[test]
+    fn test_reset_plugin_and_dependents() {
+        let mut context = Context::new();
+        assert_eq!(*context.get_data(A), 2);
+        *context.get_data_mut(B) = 10;
+
+        context.reset_plugin_and_dependents(B);
+        assert!(context.try_get_initialized_data(A).is_err());
+        assert!(context.try_get_initialized_data(B).is_err());
+        assert_eq!(context.try_get_initialized_data(C), Ok(&0));
+
+        *context.get_data_mut(C) = 1;
+        assert_eq!(*context.get_data(A), 3);
+        let graph = context.plugin_dependency_graph();
+        let [a, b, c] = [
+            std::any::type_name::<A>(),
+            std::any::type_name::<B>(),
+            std::any::type_name::<C>(),
+        ];
+        assert_eq!(graph.initialized, vec![c, b, a]);
+        assert_eq!(graph.edges, vec![(a, b), (b, c)]);
+    }
+
+    thread_local! {
+        static FINALIZED: std::cell::RefCell<Vec<(&'static str, usize)>> =
+            const { std::cell::RefCell::new(Vec::new()) };
+    }
+
+    struct FinalA;
+    impl DataPlugin for FinalA {
+        type DataContainer = usize;
+        fn init(context: &Context) -> Self::DataContainer {
+            *context.get_data(FinalB) + 1
+        }
+        fn finalize(data: &mut Self::DataContainer, context: &Context) {
+            // Dependencies are still available
+            *data += *context.get_data(FinalB);
+            FINALIZED.with_borrow_mut(|finalized| finalized.push(("A", *data)));
+        }
+    }
+
+    struct FinalB;
+    impl DataPlugin for FinalB {
+        type DataContainer = usize;
+        fn init(_context: &Context) -> Self::DataContainer {
+            10
+        }
+        fn finalize(data: &mut Self::DataContainer, _context: &Context) {
+            FINALIZED.with_borrow_mut(|finalized| finalized.push(("B", *data)));
+        }
+    }
+
+    #[test]
+    fn test_finalize_in_reverse_initialization_order() {
+        FINALIZED.with_borrow_mut(Vec::clear);
+        let mut context = Context::new();
+        context.get_data(FinalA);
+        context.finalize();
+        assert_eq!(
+            FINALIZED.with_borrow(Clone::clone),
+            vec![("A", 21), ("B", 10)]
+        );
+        // Finalized data stays readable, and finalizing again is a no-op
+        assert_eq!(*context.get_data(FinalA), 21);
+        context.finalize();
+        drop(context);
+        assert_eq!(FINALIZED.with_borrow(Vec::len), 2);
+    }
+
+    #[test]
+    fn test_finalize_on_drop() {
+        FINALIZED.with_borrow_mut(Vec::clear);
+        {
+            let mut context = Context::new();
+            *context.get_data_mut(FinalB) = 5;
+        }
+        assert_eq!(FINALIZED.with_borrow(Clone::clone), vec![("B", 5)]);
+    }
+
+    struct FinalSelf;
+    impl DataPlugin for FinalSelf {
+        type DataContainer = usize;
+        fn init(_context: &Context) -> Self::DataContainer {
+            0
+        }
+        fn finalize(_data: &mut Self::DataContainer, context: &Context) {
+            context.get_data(FinalSelf);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "while it was being finalized")]
+    fn test_finalizer_reading_own_plugin_panics() {
+        let mut context = Context::new();
+        context.get_data(FinalSelf);
+        context.finalize();
+    }
+
+    struct FinalReadsUninitialized;
+    impl DataPlugin for FinalReadsUninitialized {
+        type DataContainer = usize;
+        fn init(_context: &Context) -> Self::DataContainer {
+            0
+        }
+        fn finalize(_data: &mut Self::DataContainer, context: &Context) {
+            context.get_data(FinalB);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "during finalization but was never initialized")]
+    fn test_finalizer_reading_uninitialized_plugin_panics() {
+        let mut context = Context::new();
+        context.get_data(FinalReadsUninitialized);
+        context.finalize();
+    }
+
+    #[test]
+    fn test_unregistered_plugins_register_on_demand() {
+        let context = Context::new();
+        assert_eq!(*context.get_data(DependsOnUnregistered), 1);
+        assert_eq!(*context.get_data(Unregistered), 0);
+
+        let names = context
+            .registered_plugins()
+            .iter()
+            .map(|descriptor| descriptor.name)
+            .collect::<Vec<_>>();
+        assert!(names.contains(&std::any::type_name::<Unregistered>()));
+        let graph = context.plugin_dependency_graph();
+        assert!(graph.edges.contains(&(
+            std::any::type_name::<DependsOnUnregistered>(),
+            std::any::type_name::<Unregistered>()
+        )));
+    }
+
+    #[test]
+    fn test_unregistered_plugins_register_on_demand_mut() {
+        let mut context = Context::new();
+        *context.get_data_mut(Unregistered) = 2;
+        assert_eq!(*context.get_data_mut(DependsOnUnregistered), 3);
+        let descriptor = context
+            .initialized_plugins()
+            .into_iter()
+            .find(|descriptor| descriptor.short_name() == "Unregistered")
+            .unwrap();
+        assert_eq!(descriptor.module_path, "context_plugin_global::test");
+        assert_eq!(descriptor.description, None);
+    }
+
+    #[test]
+    fn test_unregistered() {
+        let context = Context::new();
+        assert_eq!(
+            context.try_get_initialized_data(Unregistered),
+            Err(ContextError::Unregistered {
+                plugin: std::any::type_name::<Unregistered>(),
+                suggestion: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_unregistered_suggestion() {
+        let context = new_context();
+        let error = context.try_get_initialized_data(Cyclik).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Data plugin `context_plugin_global::test::Cyclik` is not registered \
+             (did you mean `context_plugin_global::test::Cyclic`?)"
+        );
+    }
+
+    #[test]
+    fn test_plugin_descriptors() {
+        let context = new_context();
+        let registered = context.registered_plugins();
+        let c = registered
+            .iter()
+            .find(|descriptor| descriptor.name == std::any::type_name::<C>())
+            .unwrap();
+        assert_eq!(c.short_name(), "C");
+        assert_eq!(c.module_path, "context_plugin_global::test");
+        assert_eq!(c.data_container, "usize");
+        assert_eq!(c.description, None);
+
+        let described = registered
+            .iter()
+            .find(|descriptor| descriptor.short_name() == "Described")
+            .unwrap();
+        assert_eq!(described.description, Some("A plugin with a description"));
+
+        assert!(context.initialized_plugins().is_empty());
+        context.get_data(B);
+        let initialized = context
+            .initialized_plugins()
+            .iter()
+            .map(|descriptor| descriptor.short_name())
+            .collect::<Vec<_>>();
+        assert_eq!(initialized, vec!["C", "B"]);
+    }
+
+    #[test]
+    fn test_not_initialized() {
+        let context = Context::new();
+        assert_eq!(
+            context.try_get_initialized_data(C),
+            Err(ContextError::NotInitialized {
+                plugin: std::any::type_name::<C>(),
+            })
+        );
+        context.get_data(A);
+        assert_eq!(context.try_get_initialized_data(C), Ok(&0));
+    }
+
+    #[test]
+    fn test_reentrant_initialization() {
+        let mut context = Context::new();
+        assert_eq!(
+            *context.get_data_mut(TryCyclic),
+            Err(ContextError::ReentrantInitialization {
+                plugin: std::any::type_name::<TryCyclic>(),
+            })
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Cyclic` was accessed while it was being initialized")]
+    fn test_reentrant_initialization_panics() {
+        let context = Context::new();
+        context.get_data(Cyclic);
+    }
+
+    define_data_plugin!(ProfiledOuter, Vec<u8>, |context: &Context| {
+        let inner = context.get_data(ProfiledInner).len();
+        vec![0; 1000 + inner - 4000]
+    });
+
+    define_data_plugin!(ProfiledInner, Vec<u8>, |_context| {
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        vec![0; 4000]
+    });
+
+    #[test]
+    fn test_init_profile() {
+        let context = Context::new();
+        context.get_data(ProfiledOuter);
+        assert!(context.init_profile().is_empty());
+
+        let context = ContextBuilder::new().with_init_profiling().build();
+        context.get_data(ProfiledOuter);
+        // Already initialized, so not profiled again
+        context.get_data(ProfiledInner);
+        let profile = context.init_profile();
+        assert_eq!(profile.len(), 2);
+        let (inner, outer) = (profile[0], profile[1]);
+        assert!(inner.name.ends_with("ProfiledInner"));
+        assert!(outer.name.ends_with("ProfiledOuter"));
+
+        assert!(inner.exclusive_time >= std::time::Duration::from_millis(5));
+        assert_eq!(inner.inclusive_time, inner.exclusive_time);
+        assert!(outer.inclusive_time >= inner.inclusive_time);
+        assert_eq!(
+            outer.exclusive_time,
+            outer.inclusive_time - inner.inclusive_time
+        );
+
+        if cfg!(not(feature = "count-allocations")) {
+            assert_eq!(inner.exclusive_bytes, None);
+            assert_eq!(outer.inclusive_bytes, None);
+            return;
+        }
+        let inner_bytes = inner.exclusive_bytes.unwrap();
+        assert!((4000..4100).contains(&inner_bytes), "{inner_bytes}");
+        let outer_bytes = outer.exclusive_bytes.unwrap();
+        assert!((1000..3000).contains(&outer_bytes), "{outer_bytes}");
+        assert_eq!(
+            outer.inclusive_bytes.unwrap(),
+            outer_bytes + inner.inclusive_bytes.unwrap()
+        );
+    }
 }
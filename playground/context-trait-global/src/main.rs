@@ -1,9 +1,9 @@
 mod context {
-    use polonius_the_crab::prelude::*;
     use std::{
-        any::{Any, TypeId},
-        cell::{OnceCell, RefCell},
-        collections::{HashMap, HashSet},
+        any::{Any, TypeId, type_name},
+        cell::{Cell, OnceCell, RefCell, UnsafeCell},
+        cmp::Ordering,
+        collections::{BTreeMap, HashMap, HashSet, VecDeque},
         sync::{LazyLock, Mutex},
     };
 
@@ -28,62 +28,489 @@ mod context {
             .collect::<Vec<_>>()
     }
 
+    type CloneData = fn(&dyn Any) -> Box<dyn Any>;
+
+    // Plugins whose data overlays may copy, registered with `register_clone_plugin!`
+    static CLONE_FNS: LazyLock<Mutex<HashMap<TypeId, CloneData>>> =
+        LazyLock::new(|| Mutex::new(HashMap::new()));
+
+    fn clone_data<T: DataPlugin>(data: &dyn Any) -> Box<dyn Any>
+    where
+        T::DataContainer: Clone,
+    {
+        Box::new(data.downcast_ref::<T::DataContainer>().unwrap().clone())
+    }
+
+    pub fn add_plugin_clone<T: DataPlugin>()
+    where
+        T::DataContainer: Clone,
+    {
+        CLONE_FNS
+            .lock()
+            .unwrap()
+            .insert(TypeId::of::<T>(), clone_data::<T>);
+    }
+
+    /// Separates instances of the same plugin's data, e.g. one per pathogen.
+    pub type Namespace = &'static str;
+
+    /// The namespace used by `get_data`, `get_data_mut` and `set_data`, except
+    /// in a `NamespacedContext` and in plans and hooks it scheduled.
+    pub const DEFAULT_NAMESPACE: Namespace = "";
+
     pub trait Context {
-        fn get_data<T: DataPlugin>(&self, _plugin: T) -> &T::DataContainer;
-        fn get_data_mut<T: DataPlugin>(&mut self, _plugin: T) -> &mut T::DataContainer;
+        fn get_data_in<T: DataPlugin>(&self, namespace: Namespace) -> &T::DataContainer;
+        fn get_data_mut_in<T: DataPlugin>(&mut self, namespace: Namespace)
+        -> &mut T::DataContainer;
+        /// Replaces the plugin's data in `namespace`, initializing it if needed.
+        fn set_data_in<T: DataPlugin>(&mut self, namespace: Namespace, data: T::DataContainer);
+        fn get_current_time(&self) -> f64;
+        fn add_plan(&mut self, time: f64, plan: impl FnOnce(&mut BaseContext) + 'static);
+        /// The namespace used by `get_data`, `get_data_mut` and `set_data`.
+        fn namespace(&self) -> Namespace;
+
+        fn get_data<T: DataPlugin>(&self, _plugin: T) -> &T::DataContainer {
+            self.get_data_in::<T>(self.namespace())
+        }
+        fn get_data_mut<T: DataPlugin>(&mut self, _plugin: T) -> &mut T::DataContainer {
+            self.get_data_mut_in::<T>(self.namespace())
+        }
+        /// Replaces the plugin's data, initializing the plugin if needed.
+        fn set_data<T: DataPlugin>(&mut self, data: T::DataContainer) {
+            self.set_data_in::<T>(self.namespace(), data);
+        }
+    }
+
+    type Finalizer = fn(&mut dyn Any, &NamespacedContext<BaseContext>);
+
+    fn finalize_data<T: DataPlugin>(data: &mut dyn Any, context: &NamespacedContext<BaseContext>) {
+        T::finalize(data.downcast_mut::<T::DataContainer>().unwrap(), context);
+    }
+
+    type InitializedHook = fn(&mut NamespacedContext<BaseContext>);
+
+    fn on_initialized<T: DataPlugin>(context: &mut NamespacedContext<BaseContext>) {
+        T::on_initialized(context);
+    }
+
+    type PluginSlot = Box<OnceCell<Box<dyn Any>>>;
+
+    type PluginKey = (TypeId, Namespace);
+
+    type Plan = Box<dyn FnOnce(&mut BaseContext)>;
+
+    /// Orders plans by time, then the order they were added in.
+    #[derive(Debug, Clone, Copy)]
+    struct PlanKey {
+        time: f64,
+        id: usize,
+    }
+    impl PartialEq for PlanKey {
+        fn eq(&self, other: &Self) -> bool {
+            self.cmp(other) == Ordering::Equal
+        }
+    }
+    impl Eq for PlanKey {}
+    impl PartialOrd for PlanKey {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+    impl Ord for PlanKey {
+        fn cmp(&self, other: &Self) -> Ordering {
+            self.time
+                .total_cmp(&other.time)
+                .then(self.id.cmp(&other.id))
+        }
     }
 
     pub struct BaseContext {
-        data_plugins: HashMap<TypeId, OnceCell<Box<dyn Any>>>,
+        data_plugins: UnsafeCell<HashMap<PluginKey, PluginSlot>>,
+        // Plugins in the order their initializers finished, with their finalizers
+        initialization_order: RefCell<Vec<(PluginKey, Finalizer)>>,
+        // `on_initialized` hooks of plugins initialized through a shared
+        // reference, waiting for the next call that has `&mut self`
+        pending_hooks: RefCell<VecDeque<(PluginKey, InitializedHook)>>,
+        running_hooks: bool,
+        // The namespace of the running plan, used by `get_data` and friends
+        namespace: Cell<Namespace>,
+        // Each plan runs in the namespace it was added in
+        plans: BTreeMap<PlanKey, (Namespace, Plan)>,
+        // Serializable descriptions of plans, by plan id, for checkpoints
+        plan_descriptors: HashMap<usize, checkpoint::PlanDescriptor>,
+        next_plan_id: usize,
+        current_time: f64,
+        // The plugin instance whose finalizer is running, if any
+        finalizing: Cell<Option<PluginKey>>,
+        finalized: bool,
+    }
+
+    impl Default for BaseContext {
+        fn default() -> Self {
+            Self::new()
+        }
     }
 
     impl BaseContext {
         pub fn new() -> Self {
             let mut context = BaseContext {
-                data_plugins: HashMap::new(),
+                data_plugins: UnsafeCell::new(HashMap::new()),
+                initialization_order: RefCell::new(Vec::new()),
+                pending_hooks: RefCell::new(VecDeque::new()),
+                running_hooks: false,
+                namespace: Cell::new(DEFAULT_NAMESPACE),
+                plans: BTreeMap::new(),
+                plan_descriptors: HashMap::new(),
+                next_plan_id: 0,
+                current_time: 0.0,
+                finalizing: Cell::new(None),
+                finalized: false,
             };
             // Register all data plugins from global list
             for plugin_type_id in get_plugin_ids() {
-                context.data_plugins.insert(plugin_type_id, OnceCell::new());
+                context.data_plugins.get_mut().insert(
+                    (plugin_type_id, DEFAULT_NAMESPACE),
+                    Box::new(OnceCell::new()),
+                );
             }
             context
         }
+
+        // Plugins that weren't registered on startup (such as monomorphizations
+        // of generic plugins) or plugins in other namespaces get a slot the first
+        // time they are accessed
+        fn slot<T: DataPlugin>(&self, namespace: Namespace) -> &OnceCell<Box<dyn Any>> {
+            // SAFETY: an initializer reading another namespace's instance gets
+            //  here while `&OnceCell`s from outer `get_data_in` calls (one per
+            //  plugin and namespace still being initialized) are alive. They point
+            //  into the boxed slots, which growing the table doesn't move, and
+            //  `or_insert_with` never replaces an existing key. The closure only
+            //  allocates a cell, so no other access to the map overlaps this one.
+            let data_plugins = unsafe { &mut *self.data_plugins.get() };
+            data_plugins
+                .entry((TypeId::of::<T>(), namespace))
+                .or_insert_with(|| Box::new(OnceCell::new()))
+        }
+
+        fn init_data<T: DataPlugin>(&self, namespace: Namespace) -> T::DataContainer {
+            let key = (TypeId::of::<T>(), namespace);
+            // A finalizer's own slot is empty while it runs; filling it (or any
+            // other slot) now would leave data that is never finalized
+            if let Some(finalizing) = self.finalizing.get() {
+                let plugin = type_name::<T>();
+                if finalizing == key {
+                    panic!(
+                        "Data plugin `{plugin}` was accessed through the context while it was being finalized in namespace {namespace:?}"
+                    );
+                }
+                panic!(
+                    "Data plugin `{plugin}` was accessed during finalization but was never initialized in namespace {namespace:?}"
+                );
+            }
+            // The initializer's `get_data` reads the instance's own namespace,
+            // not that of whoever happened to access it first
+            let outer = self.namespace.replace(namespace);
+            let data = T::init(self);
+            self.namespace.set(outer);
+            self.initialization_order
+                .borrow_mut()
+                .push((key, finalize_data::<T>));
+            self.pending_hooks
+                .borrow_mut()
+                .push_back((key, on_initialized::<T>));
+            data
+        }
+
+        // A safe point: no references into the context are outstanding, so
+        // hooks can mutate it. Hooks may initialize more plugins, queueing
+        // their hooks in turn; those run after the current hook, in order.
+        fn run_pending_hooks(&mut self) {
+            if self.running_hooks {
+                return;
+            }
+            self.running_hooks = true;
+            while let Some(((_, namespace), hook)) = self.pending_hooks.get_mut().pop_front() {
+                hook(&mut NamespacedContext::new(self, namespace));
+            }
+            self.running_hooks = false;
+        }
+
+        // Returns the new plan's id
+        fn insert_plan(&mut self, time: f64, plan: Plan) -> usize {
+            assert!(
+                time >= self.current_time,
+                "Time {time} is in the past (current time is {})",
+                self.current_time
+            );
+            let id = self.next_plan_id;
+            self.next_plan_id += 1;
+            self.plans
+                .insert(PlanKey { time, id }, (self.namespace.get(), plan));
+            id
+        }
+
+        // Runs `f` with `namespace` as the namespace of `get_data` and friends
+        fn in_namespace(&mut self, namespace: Namespace, f: impl FnOnce(&mut BaseContext)) {
+            let outer = self.namespace.replace(namespace);
+            f(self);
+            self.namespace.set(outer);
+        }
+
+        /// Runs plans in time order until none are left.
+        pub fn execute(&mut self) {
+            self.run_pending_hooks();
+            while let Some((key, (namespace, plan))) = self.plans.pop_first() {
+                self.current_time = key.time;
+                self.plan_descriptors.remove(&key.id);
+                self.in_namespace(namespace, plan);
+                self.run_pending_hooks();
+            }
+        }
+
+        /// Finalizes every plugin instance, in every namespace, through
+        /// `DataPlugin::finalize`, last initialized first. Each finalizer gets a
+        /// `NamespacedContext` for the namespace its instance lives in. Calling
+        /// this again does nothing; dropping an unfinalized context calls it.
+        pub fn finalize(&mut self) {
+            if self.finalized {
+                return;
+            }
+            self.finalized = true;
+            let initialization_order = self.initialization_order.get_mut().clone();
+            for (key, finalizer) in initialization_order.iter().rev() {
+                // The finalizer borrows its own data mutably and the whole context
+                // immutably, so the data leaves its slot for the duration
+                let Some(mut data) = self.data_plugins.get_mut().get_mut(key).unwrap().take()
+                else {
+                    continue;
+                };
+                let (_, namespace) = *key;
+                self.finalizing.set(Some(*key));
+                finalizer(data.as_mut(), &NamespacedContext::new(self, namespace));
+                self.finalizing.set(None);
+                let _ = self.data_plugins.get_mut().get_mut(key).unwrap().set(data);
+            }
+        }
+    }
+
+    impl Drop for BaseContext {
+        fn drop(&mut self) {
+            // A finalizer that panics (say, on a broken invariant) would abort
+            // the process if the context is dropped during a panic
+            if !std::thread::panicking() {
+                self.finalize();
+            }
+        }
     }
 
     impl Context for BaseContext {
-        fn get_data<T: DataPlugin>(&self, _plugin: T) -> &T::DataContainer {
-            let type_id = TypeId::of::<T>();
-            self.data_plugins
-                .get(&type_id)
-                .expect("Data plugin not registered")
-                .get_or_init(|| Box::new(T::init(self)))
+        fn get_data_in<T: DataPlugin>(&self, namespace: Namespace) -> &T::DataContainer {
+            self.slot::<T>(namespace)
+                .get_or_init(|| Box::new(self.init_data::<T>(namespace)))
                 .downcast_ref::<T::DataContainer>()
                 .unwrap()
         }
 
-        fn get_data_mut<T: DataPlugin>(&mut self, _plugin: T) -> &mut T::DataContainer {
-            let mut self_shadow = self;
-            let type_id = TypeId::of::<T>();
-            // If the data plugin is already initialized return mutable reference
-            // Use polonius to address borrow checker limitations
-            polonius!(|self_shadow| -> &'polonius mut T::DataContainer {
-                let cell = self_shadow
-                    .data_plugins
-                    .get_mut(&type_id)
-                    .expect("Data plugin not registered");
-                if let Some(any) = cell.get_mut() {
-                    polonius_return!(any.downcast_mut::<T::DataContainer>().unwrap());
-                }
-            });
-            // Initialize the data plugin
-            let data = T::init(self_shadow);
-            let cell = self_shadow.data_plugins.get_mut(&type_id).unwrap();
-            let _ = cell.set(Box::new(data));
-            cell.get_mut()
+        fn get_data_mut_in<T: DataPlugin>(
+            &mut self,
+            namespace: Namespace,
+        ) -> &mut T::DataContainer {
+            // Initializing may queue an `on_initialized` hook, which needs
+            // `&mut self` and so can only run once the shared borrow from
+            // `get_data_in` is gone; the slot is looked up again after it ran
+            self.get_data_in::<T>(namespace);
+            self.run_pending_hooks();
+            self.data_plugins
+                .get_mut()
+                .get_mut(&(TypeId::of::<T>(), namespace))
+                .and_then(|cell| cell.get_mut())
                 .unwrap()
                 .downcast_mut::<T::DataContainer>()
                 .unwrap()
         }
+
+        fn set_data_in<T: DataPlugin>(&mut self, namespace: Namespace, data: T::DataContainer) {
+            let key = (TypeId::of::<T>(), namespace);
+            self.slot::<T>(namespace);
+            let cell = self.data_plugins.get_mut().get_mut(&key).unwrap();
+            match cell.get_mut() {
+                Some(any) => *any.downcast_mut::<T::DataContainer>().unwrap() = data,
+                None => {
+                    let _ = cell.set(Box::new(data));
+                    self.initialization_order
+                        .get_mut()
+                        .push((key, finalize_data::<T>));
+                    self.pending_hooks
+                        .get_mut()
+                        .push_back((key, on_initialized::<T>));
+                }
+            }
+            self.run_pending_hooks();
+        }
+
+        fn get_current_time(&self) -> f64 {
+            self.current_time
+        }
+
+        fn add_plan(&mut self, time: f64, plan: impl FnOnce(&mut BaseContext) + 'static) {
+            self.insert_plan(time, Box::new(plan));
+            self.run_pending_hooks();
+        }
+
+        fn namespace(&self) -> Namespace {
+            self.namespace.get()
+        }
+    }
+
+    type Commit<C> = fn(&mut C, Namespace, Box<dyn Any>);
+
+    fn commit_data<T: DataPlugin, C: Context>(
+        base: &mut C,
+        namespace: Namespace,
+        data: Box<dyn Any>,
+    ) {
+        base.set_data_in::<T>(namespace, *data.downcast::<T::DataContainer>().unwrap());
+    }
+
+    /// A context that reads through to `base` but copies plugin data into an
+    /// overlay the first time it is mutated, for evaluating counterfactuals.
+    /// Dropping the overlay (or calling `discard`) leaves `base` unchanged;
+    /// `commit` writes the modified data back.
+    ///
+    /// Plugins first accessed through the overlay are initialized in `base`.
+    /// Mutating a plugin's data copies it, so `get_data_mut` panics for plugins
+    /// not registered with `register_clone_plugin!`; `set_data` works for any.
+    pub struct OverlayContext<'a, C: Context> {
+        base: &'a mut C,
+        overlay: HashMap<PluginKey, (Box<dyn Any>, Commit<C>)>,
+        // Plans are only added to `base` on commit
+        plans: Vec<(f64, Plan)>,
+    }
+
+    impl<'a, C: Context> OverlayContext<'a, C> {
+        pub fn new(base: &'a mut C) -> Self {
+            OverlayContext {
+                base,
+                overlay: HashMap::new(),
+                plans: Vec::new(),
+            }
+        }
+
+        /// Writes every plugin modified through the overlay back to `base`.
+        pub fn commit(self) {
+            for ((_, namespace), (data, commit)) in self.overlay {
+                commit(self.base, namespace, data);
+            }
+            for (time, plan) in self.plans {
+                self.base.add_plan(time, plan);
+            }
+        }
+
+        pub fn discard(self) {}
+    }
+
+    impl<C: Context> Context for OverlayContext<'_, C> {
+        fn get_data_in<T: DataPlugin>(&self, namespace: Namespace) -> &T::DataContainer {
+            match self.overlay.get(&(TypeId::of::<T>(), namespace)) {
+                Some((data, _)) => data.downcast_ref::<T::DataContainer>().unwrap(),
+                None => self.base.get_data_in::<T>(namespace),
+            }
+        }
+
+        fn get_data_mut_in<T: DataPlugin>(
+            &mut self,
+            namespace: Namespace,
+        ) -> &mut T::DataContainer {
+            let base = &*self.base;
+            let (data, _) = self
+                .overlay
+                .entry((TypeId::of::<T>(), namespace))
+                .or_insert_with(|| {
+                    let clone = CLONE_FNS
+                        .lock()
+                        .unwrap()
+                        .get(&TypeId::of::<T>())
+                        .copied()
+                        .unwrap_or_else(|| {
+                            panic!(
+                                "Data plugin `{}` can't be mutated through an overlay: it isn't \
+                                 registered with `register_clone_plugin!`",
+                                type_name::<T>()
+                            )
+                        });
+                    (clone(base.get_data_in::<T>(namespace)), commit_data::<T, C>)
+                });
+            data.downcast_mut::<T::DataContainer>().unwrap()
+        }
+
+        fn set_data_in<T: DataPlugin>(&mut self, namespace: Namespace, data: T::DataContainer) {
+            self.overlay.insert(
+                (TypeId::of::<T>(), namespace),
+                (Box::new(data), commit_data::<T, C>),
+            );
+        }
+
+        fn get_current_time(&self) -> f64 {
+            self.base.get_current_time()
+        }
+
+        fn add_plan(&mut self, time: f64, plan: impl FnOnce(&mut BaseContext) + 'static) {
+            self.plans.push((time, Box::new(plan)));
+        }
+
+        // An overlay opened in a namespaced plan or `NamespacedContext` stays in
+        // that namespace
+        fn namespace(&self) -> Namespace {
+            self.base.namespace()
+        }
+    }
+
+    /// A view of `base` in which `get_data`, `get_data_mut` and `set_data` use
+    /// `namespace`, so extension traits operate on one instance of a model.
+    /// Explicitly namespaced accesses are passed through unchanged. Plans added
+    /// through it run in `namespace`, as do the `on_initialized` hooks and
+    /// finalizers of plugins in `namespace`.
+    pub struct NamespacedContext<'a, C: Context> {
+        base: &'a mut C,
+        namespace: Namespace,
+    }
+
+    impl<'a, C: Context> NamespacedContext<'a, C> {
+        pub fn new(base: &'a mut C, namespace: Namespace) -> Self {
+            NamespacedContext { base, namespace }
+        }
+    }
+
+    impl<C: Context> Context for NamespacedContext<'_, C> {
+        fn get_data_in<T: DataPlugin>(&self, namespace: Namespace) -> &T::DataContainer {
+            self.base.get_data_in::<T>(namespace)
+        }
+
+        fn get_data_mut_in<T: DataPlugin>(
+            &mut self,
+            namespace: Namespace,
+        ) -> &mut T::DataContainer {
+            self.base.get_data_mut_in::<T>(namespace)
+        }
+
+        fn set_data_in<T: DataPlugin>(&mut self, namespace: Namespace, data: T::DataContainer) {
+            self.base.set_data_in::<T>(namespace, data);
+        }
+
+        fn get_current_time(&self) -> f64 {
+            self.base.get_current_time()
+        }
+
+        fn add_plan(&mut self, time: f64, plan: impl FnOnce(&mut BaseContext) + 'static) {
+            let namespace = self.namespace;
+            self.base.add_plan(time, move |context: &mut BaseContext| {
+                context.in_namespace(namespace, plan)
+            });
+        }
+
+        fn namespace(&self) -> Namespace {
+            self.namespace
+        }
     }
 
     pub trait DataPlugin: 'static {
@@ -91,17 +518,58 @@ mod context {
 
         // This should be context: &impl Context
         fn init(context: &impl Context) -> Self::DataContainer;
+
+        /// Called after the plugin is initialized, at the next point where the
+        /// context can be borrowed mutably: the next `get_data_mut`, `set_data`
+        /// or `add_plan` call, or before the next plan runs. Use it to schedule
+        /// plans; `init` only gets shared access.
+        fn on_initialized(_context: &mut impl Context) {}
+
+        /// Called with one namespace's instance of the data when the context is
+        /// finalized. `context` resolves `get_data` in that namespace; it can't
+        /// be used to read this instance, or any instance that was never
+        /// initialized.
+        fn finalize(_data: &mut Self::DataContainer, _context: &impl Context) {}
     }
 
+    /// Defines a data plugin and registers it with `DATA_PLUGINS`.
+    ///
+    /// ```rust,ignore
+    /// define_data_plugin!(
+    ///     /// Doc comments and attributes are applied to the plugin type
+    ///     #[derive(Debug)]
+    ///     pub CounterPlugin<T: Clone>,
+    ///     Vec<T>,
+    ///     |_context| Vec::new(),
+    ///     "Optional description, added to the plugin's docs"
+    /// );
+    /// ```
+    ///
+    /// The initializer is written as a closure taking `&impl Context`, without
+    /// a type annotation: `impl Trait` can't be written on a binding, so there
+    /// would be no way to check one. Generic plugins
+    /// are constructed with `CounterPlugin::<u32>::new()`; since their
+    /// monomorphizations aren't known up front, each one is registered by the
+    /// context the first time it is accessed.
     #[macro_export]
     macro_rules! define_data_plugin {
-        ($data_plugin:ident, $data_container:ty, |$ctx:ident| $body:expr) => {
-            struct $data_plugin;
+        (
+            $(#[$attr:meta])*
+            $vis:vis $data_plugin:ident,
+            $data_container:ty,
+            |$ctx:tt| $body:expr
+            $(, $description:literal)? $(,)?
+        ) => {
+            $(#[$attr])*
+            $(#[doc = $description])?
+            $vis struct $data_plugin;
 
             impl $crate::context::DataPlugin for $data_plugin {
                 type DataContainer = $data_container;
 
-                fn init($ctx: &impl Context) -> Self::DataContainer {
+                // The initializer body is usually a block
+                #[allow(unused_braces)]
+                fn init($ctx: &impl $crate::context::Context) -> Self::DataContainer {
                     $body
                 }
             }
@@ -113,6 +581,452 @@ mod context {
                 }
             }
         };
+        (
+            $(#[$attr:meta])*
+            $vis:vis $data_plugin:ident<$($param:ident $(: $bound:path)?),+ $(,)?>,
+            $data_container:ty,
+            |$ctx:tt| $body:expr
+            $(, $description:literal)? $(,)?
+        ) => {
+            $(#[$attr])*
+            $(#[doc = $description])?
+            $vis struct $data_plugin<$($param),+>(::std::marker::PhantomData<fn() -> ($($param,)+)>);
+
+            impl<$($param),+> $data_plugin<$($param),+> {
+                #[allow(dead_code)]
+                $vis const fn new() -> Self {
+                    $data_plugin(::std::marker::PhantomData)
+                }
+            }
+
+            impl<$($param),+> ::std::default::Default for $data_plugin<$($param),+> {
+                fn default() -> Self {
+                    Self::new()
+                }
+            }
+
+            impl<$($param: 'static $(+ $bound)?),+> $crate::context::DataPlugin
+                for $data_plugin<$($param),+>
+            {
+                type DataContainer = $data_container;
+
+                // The initializer body is usually a block
+                #[allow(unused_braces)]
+                fn init($ctx: &impl $crate::context::Context) -> Self::DataContainer {
+                    $body
+                }
+            }
+        };
+    }
+
+    /// Lets overlays copy a plugin's data (which must implement `Clone`) on
+    /// its first mutation.
+    #[macro_export]
+    macro_rules! register_clone_plugin {
+        ($data_plugin:ty) => {
+            paste::paste! {
+                #[ctor::ctor]
+                fn [<_register_clone_plugin_$data_plugin:snake>]() {
+                    $crate::context::add_plugin_clone::<$data_plugin>()
+                }
+            }
+        };
+    }
+
+    /// Saving a running simulation to a file and resuming it later.
+    ///
+    /// A checkpoint holds the current time, the data of every initialized
+    /// plugin registered with `register_checkpoint_plugin!`, the queued plans,
+    /// which must all have been added with `add_serializable_plan`, and the
+    /// `on_initialized` hooks of registered plugins that haven't run yet.
+    /// Plugins and plans are saved under the names they were registered with,
+    /// so renaming or moving a type doesn't break old checkpoints. Plugins that
+    /// did not opt in are initialized again on first access after resuming.
+    pub mod checkpoint {
+        use super::{BaseContext, DataPlugin, InitializedHook, Namespace, PluginKey};
+        use serde::{Deserialize, Serialize, de::DeserializeOwned};
+        use std::{
+            any::{Any, TypeId},
+            collections::{HashMap, HashSet},
+            fmt, fs,
+            path::Path,
+            sync::{LazyLock, Mutex},
+        };
+
+        #[derive(Debug, Clone, PartialEq)]
+        pub enum CheckpointError {
+            /// The checkpoint file could not be read or written
+            Io { path: String, message: String },
+            /// The checkpoint file is not valid
+            Format { path: String, message: String },
+            /// A plugin's data could not be serialized or deserialized
+            Data { plugin: String, message: String },
+            /// The checkpoint contains a plugin or plan type that is not registered
+            Unregistered { name: String },
+            /// A queued plan was added with `add_plan` rather than `add_serializable_plan`
+            UnserializablePlan { time: f64 },
+        }
+
+        impl fmt::Display for CheckpointError {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                    CheckpointError::Io { path, message } => {
+                        write!(f, "Failed to access checkpoint file `{path}`: {message}")
+                    }
+                    CheckpointError::Format { path, message } => {
+                        write!(f, "Invalid checkpoint file `{path}`: {message}")
+                    }
+                    CheckpointError::Data { plugin, message } => {
+                        write!(f, "Failed to checkpoint data of `{plugin}`: {message}")
+                    }
+                    CheckpointError::Unregistered { name } => {
+                        write!(f, "`{name}` is not registered for checkpoints")
+                    }
+                    CheckpointError::UnserializablePlan { time } => write!(
+                        f,
+                        "The plan at time {time} was not added with `add_serializable_plan`"
+                    ),
+                }
+            }
+        }
+
+        impl std::error::Error for CheckpointError {}
+
+        /// A plan that can be saved in a checkpoint: its state is serialized
+        /// when it is added and deserialized when the checkpoint is loaded.
+        pub trait SerializablePlan: Serialize + DeserializeOwned + 'static {
+            /// Identifies the plan type in checkpoints; keep it when renaming the type.
+            const NAME: &'static str;
+
+            fn run(self, context: &mut BaseContext);
+        }
+
+        type SerializeData = fn(&dyn Any) -> serde_json::Result<serde_json::Value>;
+        type RestoreData =
+            fn(&mut BaseContext, Namespace, serde_json::Value) -> serde_json::Result<()>;
+        type RestorePlan = fn(&mut BaseContext, f64, serde_json::Value) -> serde_json::Result<()>;
+
+        struct PluginSerializer {
+            name: &'static str,
+            serialize: SerializeData,
+            restore: RestoreData,
+            on_initialized: InitializedHook,
+        }
+
+        // Per-plugin serializers, keyed like `DATA_PLUGINS`
+        static PLUGIN_SERIALIZERS: LazyLock<Mutex<HashMap<TypeId, PluginSerializer>>> =
+            LazyLock::new(|| Mutex::new(HashMap::new()));
+
+        static PLAN_DESERIALIZERS: LazyLock<Mutex<HashMap<&'static str, RestorePlan>>> =
+            LazyLock::new(|| Mutex::new(HashMap::new()));
+
+        fn serialize_data<T: DataPlugin>(data: &dyn Any) -> serde_json::Result<serde_json::Value>
+        where
+            T::DataContainer: Serialize,
+        {
+            serde_json::to_value(data.downcast_ref::<T::DataContainer>().unwrap())
+        }
+
+        fn restore_data<T: DataPlugin>(
+            context: &mut BaseContext,
+            namespace: Namespace,
+            value: serde_json::Value,
+        ) -> serde_json::Result<()>
+        where
+            T::DataContainer: DeserializeOwned,
+        {
+            let data = serde_json::from_value::<T::DataContainer>(value)?;
+            context.restore_data::<T>(namespace, data);
+            Ok(())
+        }
+
+        fn restore_plan<P: SerializablePlan>(
+            context: &mut BaseContext,
+            time: f64,
+            value: serde_json::Value,
+        ) -> serde_json::Result<()> {
+            let plan = serde_json::from_value::<P>(value)?;
+            context.add_serializable_plan(time, plan);
+            Ok(())
+        }
+
+        pub fn add_plugin_serializer<T: DataPlugin>(name: &'static str)
+        where
+            T::DataContainer: Serialize + DeserializeOwned,
+        {
+            PLUGIN_SERIALIZERS.lock().unwrap().insert(
+                TypeId::of::<T>(),
+                PluginSerializer {
+                    name,
+                    serialize: serialize_data::<T>,
+                    restore: restore_data::<T>,
+                    on_initialized: super::on_initialized::<T>,
+                },
+            );
+        }
+
+        pub fn add_plan_deserializer<P: SerializablePlan>() {
+            PLAN_DESERIALIZERS
+                .lock()
+                .unwrap()
+                .insert(P::NAME, restore_plan::<P>);
+        }
+
+        pub(super) struct PlanDescriptor {
+            name: &'static str,
+            data: serde_json::Value,
+        }
+
+        #[derive(Serialize, Deserialize)]
+        struct Checkpoint {
+            time: f64,
+            // In initialization order, so finalizers still run in reverse order
+            plugins: Vec<PluginEntry>,
+            // In execution order
+            plans: Vec<PlanEntry>,
+            // Hooks of restored plugins, in the order they were queued
+            pending_hooks: Vec<HookEntry>,
+        }
+
+        #[derive(Serialize, Deserialize)]
+        struct HookEntry {
+            plugin: String,
+            namespace: String,
+        }
+
+        #[derive(Serialize, Deserialize)]
+        struct PluginEntry {
+            plugin: String,
+            namespace: String,
+            data: serde_json::Value,
+        }
+
+        #[derive(Serialize, Deserialize)]
+        struct PlanEntry {
+            time: f64,
+            namespace: String,
+            plan: String,
+            data: serde_json::Value,
+        }
+
+        // Namespaces loaded from checkpoints, leaked once each so that loading
+        // many checkpoints doesn't leak the same names over and over
+        static NAMESPACES: LazyLock<Mutex<HashSet<Namespace>>> =
+            LazyLock::new(|| Mutex::new(HashSet::from([super::DEFAULT_NAMESPACE])));
+
+        fn intern_namespace(namespace: &str) -> Namespace {
+            let mut namespaces = NAMESPACES.lock().unwrap();
+            match namespaces.get(namespace) {
+                Some(interned) => interned,
+                None => {
+                    let leaked: Namespace = Box::leak(namespace.into());
+                    namespaces.insert(leaked);
+                    leaked
+                }
+            }
+        }
+
+        impl BaseContext {
+            /// Adds a plan that is kept when the context is checkpointed.
+            pub fn add_serializable_plan<P: SerializablePlan>(&mut self, time: f64, plan: P) {
+                let data = serde_json::to_value(&plan)
+                    .unwrap_or_else(|error| panic!("Failed to serialize plan: {error}"));
+                let id = self.insert_plan(time, Box::new(move |context| plan.run(context)));
+                self.plan_descriptors.insert(
+                    id,
+                    PlanDescriptor {
+                        name: P::NAME,
+                        data,
+                    },
+                );
+                self.run_pending_hooks();
+            }
+
+            // Like `set_data_in` for a plugin that isn't initialized yet, but
+            // without queueing `on_initialized`: its effects are in the checkpoint
+            fn restore_data<T: DataPlugin>(
+                &mut self,
+                namespace: Namespace,
+                data: T::DataContainer,
+            ) {
+                let key = (TypeId::of::<T>(), namespace);
+                self.slot::<T>(namespace);
+                let cell = self.data_plugins.get_mut().get_mut(&key).unwrap();
+                if cell.set(Box::new(data)).is_ok() {
+                    self.initialization_order
+                        .get_mut()
+                        .push((key, super::finalize_data::<T>));
+                }
+            }
+
+            /// Writes the context to `path` as JSON.
+            pub fn save_checkpoint(&self, path: impl AsRef<Path>) -> Result<(), CheckpointError> {
+                let path = path.as_ref();
+                let serializers = PLUGIN_SERIALIZERS.lock().unwrap();
+                let mut plugins = Vec::new();
+                for (key, _) in self.initialization_order.borrow().iter() {
+                    let (type_id, namespace): PluginKey = *key;
+                    let Some(serializer) = serializers.get(&type_id) else {
+                        continue;
+                    };
+                    // Safety: only the map is borrowed; see `slot`
+                    let data_plugins = unsafe { &*self.data_plugins.get() };
+                    let Some(data) = data_plugins.get(key).and_then(|cell| cell.get()) else {
+                        continue;
+                    };
+                    plugins.push(PluginEntry {
+                        plugin: serializer.name.to_string(),
+                        namespace: namespace.to_string(),
+                        data: (serializer.serialize)(data.as_ref()).map_err(|error| {
+                            CheckpointError::Data {
+                                plugin: serializer.name.to_string(),
+                                message: error.to_string(),
+                            }
+                        })?,
+                    });
+                }
+                let mut plans = Vec::new();
+                for (key, (namespace, _)) in &self.plans {
+                    let descriptor = self
+                        .plan_descriptors
+                        .get(&key.id)
+                        .ok_or(CheckpointError::UnserializablePlan { time: key.time })?;
+                    plans.push(PlanEntry {
+                        time: key.time,
+                        namespace: namespace.to_string(),
+                        plan: descriptor.name.to_string(),
+                        data: descriptor.data.clone(),
+                    });
+                }
+                // Hooks of plugins that aren't saved are queued again when the
+                // plugin is initialized after loading
+                let pending_hooks = self
+                    .pending_hooks
+                    .borrow()
+                    .iter()
+                    .filter_map(|((type_id, namespace), _)| {
+                        Some(HookEntry {
+                            plugin: serializers.get(type_id)?.name.to_string(),
+                            namespace: namespace.to_string(),
+                        })
+                    })
+                    .collect();
+                let checkpoint = Checkpoint {
+                    time: self.current_time,
+                    plugins,
+                    plans,
+                    pending_hooks,
+                };
+                let contents = serde_json::to_string_pretty(&checkpoint).unwrap();
+                fs::write(path, contents).map_err(|error| CheckpointError::Io {
+                    path: path.display().to_string(),
+                    message: error.to_string(),
+                })
+            }
+
+            /// Creates a context from a file written by `save_checkpoint`.
+            pub fn load_checkpoint(path: impl AsRef<Path>) -> Result<Self, CheckpointError> {
+                let path = path.as_ref();
+                let display = path.display().to_string();
+                let contents = fs::read_to_string(path).map_err(|error| CheckpointError::Io {
+                    path: display.clone(),
+                    message: error.to_string(),
+                })?;
+                let checkpoint =
+                    serde_json::from_str::<Checkpoint>(&contents).map_err(|error| {
+                        CheckpointError::Format {
+                            path: display,
+                            message: error.to_string(),
+                        }
+                    })?;
+
+                let mut context = BaseContext::new();
+                context.current_time = checkpoint.time;
+                let restorers = PLUGIN_SERIALIZERS
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .map(|(type_id, serializer)| {
+                        let PluginSerializer {
+                            name,
+                            restore,
+                            on_initialized,
+                            ..
+                        } = *serializer;
+                        (name, (*type_id, restore, on_initialized))
+                    })
+                    .collect::<HashMap<_, _>>();
+                let registered = |name: &str| {
+                    restorers
+                        .get(name)
+                        .copied()
+                        .ok_or_else(|| CheckpointError::Unregistered {
+                            name: name.to_string(),
+                        })
+                };
+                for entry in checkpoint.plugins {
+                    let (_, restore, _) = registered(&entry.plugin)?;
+                    let namespace = intern_namespace(&entry.namespace);
+                    restore(&mut context, namespace, entry.data).map_err(|error| {
+                        CheckpointError::Data {
+                            plugin: entry.plugin,
+                            message: error.to_string(),
+                        }
+                    })?;
+                }
+                let deserializers = PLAN_DESERIALIZERS.lock().unwrap().clone();
+                for entry in checkpoint.plans {
+                    let restore = *deserializers.get(entry.plan.as_str()).ok_or_else(|| {
+                        CheckpointError::Unregistered {
+                            name: entry.plan.clone(),
+                        }
+                    })?;
+                    // The plan is added in the namespace it was saved from
+                    let namespace = intern_namespace(&entry.namespace);
+                    context.namespace.set(namespace);
+                    let result = restore(&mut context, entry.time, entry.data);
+                    context.namespace.set(super::DEFAULT_NAMESPACE);
+                    result.map_err(|error| CheckpointError::Data {
+                        plugin: entry.plan,
+                        message: error.to_string(),
+                    })?;
+                }
+                // They run when the resumed context is first borrowed mutably
+                for entry in checkpoint.pending_hooks {
+                    let (type_id, _, hook) = registered(&entry.plugin)?;
+                    let key = (type_id, intern_namespace(&entry.namespace));
+                    context.pending_hooks.get_mut().push_back((key, hook));
+                }
+                Ok(context)
+            }
+        }
+
+        /// Opts a plugin's data (which must implement `Serialize` and
+        /// `Deserialize`) in to checkpoints, saved under `$name`.
+        #[macro_export]
+        macro_rules! register_checkpoint_plugin {
+            ($data_plugin:ty, $name:literal) => {
+                paste::paste! {
+                    #[ctor::ctor]
+                    fn [<_register_checkpoint_plugin_$data_plugin:snake>]() {
+                        $crate::context::checkpoint::add_plugin_serializer::<$data_plugin>($name)
+                    }
+                }
+            };
+        }
+
+        /// Allows plans of a `SerializablePlan` type to be restored from checkpoints.
+        #[macro_export]
+        macro_rules! register_checkpoint_plan {
+            ($plan:ty) => {
+                paste::paste! {
+                    #[ctor::ctor]
+                    fn [<_register_checkpoint_plan_$plan:snake>]() {
+                        $crate::context::checkpoint::add_plan_deserializer::<$plan>()
+                    }
+                }
+            };
+        }
     }
 }
 
@@ -121,6 +1035,8 @@ mod bool_plugin {
     use super::define_data_plugin;
 
     define_data_plugin!(BoolPlugin, bool, |_context| false);
+    crate::register_checkpoint_plugin!(BoolPlugin, "bool");
+    crate::register_clone_plugin!(BoolPlugin);
 
     pub trait BoolExt: Context {
         fn set_bool(&mut self, value: bool) {
@@ -135,9 +1051,29 @@ mod bool_plugin {
 
 mod number_plugin {
     use crate::bool_plugin::BoolExt;
-    use crate::{context::Context, define_data_plugin};
+    use crate::context::{BaseContext, Context, checkpoint::SerializablePlan};
+    use crate::{
+        define_data_plugin, register_checkpoint_plan, register_checkpoint_plugin,
+        register_clone_plugin,
+    };
+    use serde::{Deserialize, Serialize};
 
-    define_data_plugin!(NumberPlugin, u32, |_context| 0);
+    define_data_plugin!(pub NumberPlugin, u32, |_context| 0);
+    register_checkpoint_plugin!(NumberPlugin, "number");
+    register_clone_plugin!(NumberPlugin);
+
+    /// Sets the number when it runs; survives checkpoints.
+    #[derive(Serialize, Deserialize)]
+    pub struct SetNumber(pub u32);
+
+    impl SerializablePlan for SetNumber {
+        const NAME: &'static str = "set_number";
+
+        fn run(self, context: &mut BaseContext) {
+            context.set_number(self.0);
+        }
+    }
+    register_checkpoint_plan!(SetNumber);
 
     pub trait NumberExt: Context {
         fn set_number(&mut self, value: u32) {
@@ -153,43 +1089,457 @@ mod number_plugin {
     impl<T: Context + ?Sized> NumberExt for T {}
 }
 
-use bool_plugin::BoolExt;
-use context::*;
+/// Named random number streams.
+///
+/// Each stream is seeded from the base seed plus a hash of its name, so the
+/// numbers drawn from one stream don't depend on which other streams (and so
+/// which other plugins) exist or how often they are used. Streams live in the
+/// default namespace, so every namespace shares the base seed and streams.
+mod random {
+    use crate::context::{Context, DEFAULT_NAMESPACE};
+    use crate::{define_data_plugin, register_checkpoint_plugin, register_clone_plugin};
+    use rand::{
+        Rng, SeedableRng,
+        distributions::{
+            Distribution, WeightedIndex,
+            uniform::{SampleRange, SampleUniform},
+        },
+    };
+    use rand_chacha::ChaCha8Rng;
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
 
-use crate::number_plugin::NumberExt;
+    /// Identifies a random number stream; see `define_rng!`.
+    pub trait RngId: 'static {
+        /// Must be unique, since streams are looked up and seeded by name
+        const NAME: &'static str;
+    }
 
-pub fn main() {
-    // Example usage
-    let mut context = BaseContext::new();
-    assert!(!context.get_bool());
-    assert_eq!(context.get_bool_as_number(), 0);
-    assert_eq!(context.get_number(), 0);
-    context.set_bool(true);
-    assert!(context.get_bool());
-    assert_eq!(context.get_bool_as_number(), 1);
-    context.set_number(2);
-    assert_eq!(context.get_number(), 2);
-}
+    /// Defines a random number stream, named after its type.
+    ///
+    /// ```rust,ignore
+    /// define_rng!(pub ContactRng);
+    /// let contact = context.sample_range(ContactRng, 0..population);
+    /// ```
+    #[macro_export]
+    macro_rules! define_rng {
+        ($(#[$attr:meta])* $vis:vis $rng:ident) => {
+            $(#[$attr])*
+            #[derive(Debug, Clone, Copy)]
+            $vis struct $rng;
 
-#[cfg(test)]
-mod test {
-    use super::context::{BaseContext, Context};
-    use super::define_data_plugin;
+            impl $crate::random::RngId for $rng {
+                const NAME: &'static str = stringify!($rng);
+            }
+        };
+    }
 
-    define_data_plugin!(A, usize, |context| {
-        println!("Initializing A");
-        *context.get_data(B) + 1
-    });
+    #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+    pub struct RngData {
+        base_seed: u64,
+        // Streams by name, created on first use
+        streams: BTreeMap<String, ChaCha8Rng>,
+    }
 
-    define_data_plugin!(B, usize, |context| {
-        println!("Initializing B");
-        *context.get_data(C) + 1
-    });
+    define_data_plugin!(pub RngPlugin, RngData, |_context| RngData::default());
+    register_checkpoint_plugin!(RngPlugin, "rng");
+    register_clone_plugin!(RngPlugin);
 
-    define_data_plugin!(C, usize, |_context| {
-        println!("Initializing C");
-        0
+    // FNV-1a, which unlike `DefaultHasher` is stable across Rust releases
+    fn hash_name(name: &str) -> u64 {
+        name.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+        })
+    }
+
+    pub trait RandomExt: Context {
+        /// Sets the base seed and restarts every stream. The base seed is 0
+        /// until this is called.
+        fn init_random(&mut self, base_seed: u64) {
+            self.set_data_in::<RngPlugin>(
+                DEFAULT_NAMESPACE,
+                RngData {
+                    base_seed,
+                    streams: BTreeMap::new(),
+                },
+            );
+        }
+        /// Calls `f` with the generator of `R`'s stream.
+        fn with_rng<R: RngId, T>(&mut self, _rng: R, f: impl FnOnce(&mut ChaCha8Rng) -> T) -> T {
+            let data = self.get_data_mut_in::<RngPlugin>(DEFAULT_NAMESPACE);
+            if !data.streams.contains_key(R::NAME) {
+                let seed = data.base_seed.wrapping_add(hash_name(R::NAME));
+                data.streams
+                    .insert(R::NAME.to_string(), ChaCha8Rng::seed_from_u64(seed));
+            }
+            f(data.streams.get_mut(R::NAME).unwrap())
+        }
+        fn sample_range<R: RngId, T: SampleUniform>(
+            &mut self,
+            rng: R,
+            range: impl SampleRange<T>,
+        ) -> T {
+            self.with_rng(rng, |rng| rng.gen_range(range))
+        }
+        /// Returns true with probability `p`.
+        fn sample_bool<R: RngId>(&mut self, rng: R, p: f64) -> bool {
+            self.with_rng(rng, |rng| rng.gen_bool(p))
+        }
+        /// Returns an index into `weights`, chosen with probability
+        /// proportional to its weight.
+        fn sample_weighted<R: RngId>(&mut self, rng: R, weights: &[f64]) -> usize {
+            let index = WeightedIndex::new(weights)
+                .unwrap_or_else(|error| panic!("Invalid weights {weights:?}: {error}"));
+            self.with_rng(rng, |rng| index.sample(rng))
+        }
+        fn sample_distr<R: RngId, T>(&mut self, rng: R, distribution: impl Distribution<T>) -> T {
+            self.with_rng(rng, |rng| distribution.sample(rng))
+        }
+    }
+    impl<T: Context + ?Sized> RandomExt for T {}
+}
+
+/// Typed report files.
+///
+/// Model code registers a file for a row type with `add_report::<Row>(path,
+/// format)` and sends rows with `send_report(row)`. Rows are buffered, each
+/// with the time it was sent, and written when `flush_reports` is called, when
+/// a file's buffer grows past `FLUSH_THRESHOLD` or when the context is
+/// finalized. Reports are shared by all namespaces.
+mod report {
+    use crate::context::{Context, DEFAULT_NAMESPACE, DataPlugin};
+    use crate::{register_checkpoint_plugin, register_clone_plugin};
+    use serde::{Deserialize, Serialize};
+    use std::{
+        any::type_name,
+        collections::HashMap,
+        fs::{self, OpenOptions},
+        io::{self, Seek, SeekFrom, Write},
+        iter,
+        path::{Path, PathBuf},
+    };
+
+    /// Buffered bytes at which a report file is written out by `send_report`.
+    pub const FLUSH_THRESHOLD: usize = 64 * 1024;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum ReportFormat {
+        /// A header, then one line per row; rows must be flat structs
+        Csv,
+        /// One JSON object per line
+        Json,
+    }
+
+    #[derive(Serialize)]
+    struct TimedRow<'a, R> {
+        time: f64,
+        #[serde(flatten)]
+        row: &'a R,
+    }
+
+    // `csv` only writes headers for the structs it serializes itself, so
+    // serialize the row once with headers and read them back
+    fn csv_header<R: Serialize>(row: &R) -> io::Result<csv::StringRecord> {
+        let mut probe = csv::Writer::from_writer(Vec::new());
+        probe.serialize(row)?;
+        let bytes = probe.into_inner().map_err(|error| error.into_error())?;
+        Ok(csv::Reader::from_reader(bytes.as_slice())
+            .headers()?
+            .clone())
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct ReportFile {
+        path: PathBuf,
+        format: ReportFormat,
+        // Serialized rows that haven't been written yet
+        buffer: Vec<u8>,
+        // Whether the CSV header has been buffered
+        has_header: bool,
+        // Bytes this context has written to the file. Anything after them was
+        // written by a run this one was resumed from, after its checkpoint.
+        written: u64,
+    }
+
+    impl ReportFile {
+        fn push<R: Serialize>(&mut self, time: f64, row: &R) -> io::Result<()> {
+            match self.format {
+                ReportFormat::Csv => {
+                    let mut writer = csv::WriterBuilder::new()
+                        .has_headers(false)
+                        .from_writer(&mut self.buffer);
+                    if !self.has_header {
+                        let header = csv_header(row)?;
+                        writer.write_record(iter::once("time").chain(header.iter()))?;
+                        self.has_header = true;
+                    }
+                    // Formatted like the JSON `time` field, so 1 is "1.0" in both
+                    writer.write_field(serde_json::to_string(&time)?)?;
+                    writer.serialize(row)?;
+                    writer.flush()
+                }
+                ReportFormat::Json => {
+                    serde_json::to_writer(&mut self.buffer, &TimedRow { time, row })?;
+                    self.buffer.push(b'\n');
+                    Ok(())
+                }
+            }
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            // Even with nothing to write, rows past `written` are cut off: they
+            // come from an overlay's copy of this file that was then discarded
+            let stale = fs::metadata(&self.path).is_ok_and(|file| file.len() > self.written);
+            if self.buffer.is_empty() && !stale {
+                return Ok(());
+            }
+            OpenOptions::new()
+                .write(true)
+                .open(&self.path)
+                .and_then(|mut file| {
+                    file.set_len(self.written)?;
+                    file.seek(SeekFrom::Start(self.written))?;
+                    file.write_all(&self.buffer)
+                })
+                .map_err(|error| {
+                    io::Error::new(error.kind(), format!("{}: {error}", self.path.display()))
+                })?;
+            self.written += self.buffer.len() as u64;
+            self.buffer.clear();
+            Ok(())
+        }
+    }
+
+    /// Report files by the name of their row type.
+    #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+    pub struct ReportData {
+        files: HashMap<String, ReportFile>,
+    }
+
+    impl ReportData {
+        fn flush(&mut self) -> io::Result<()> {
+            self.files.values_mut().try_for_each(ReportFile::flush)
+        }
+    }
+
+    pub struct ReportPlugin;
+    impl DataPlugin for ReportPlugin {
+        type DataContainer = ReportData;
+        fn init(_context: &impl Context) -> Self::DataContainer {
+            ReportData::default()
+        }
+        fn finalize(data: &mut Self::DataContainer, _context: &impl Context) {
+            if let Err(error) = data.flush() {
+                eprintln!("Failed to write report {error}");
+            }
+        }
+    }
+    register_checkpoint_plugin!(ReportPlugin, "report");
+    register_clone_plugin!(ReportPlugin);
+
+    pub trait ReportExt: Context {
+        /// Creates (or truncates) the file that rows of type `R` are written to.
+        /// Fails if another row type already writes to `path`.
+        ///
+        /// Reports are saved in checkpoints. In a context loaded from one, a
+        /// report registered again with the same path and format is kept, and
+        /// its file continues from where it was when the checkpoint was saved.
+        fn add_report<R: Serialize + 'static>(
+            &mut self,
+            path: impl AsRef<Path>,
+            format: ReportFormat,
+        ) -> io::Result<()> {
+            let path = path.as_ref();
+            let files = &mut self
+                .get_data_mut_in::<ReportPlugin>(DEFAULT_NAMESPACE)
+                .files;
+            if let Some(file) = files.get(type_name::<R>()) {
+                if file.path == path && file.format == format {
+                    return Ok(());
+                }
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("A report for `{}` is already registered", type_name::<R>()),
+                ));
+            }
+            // Each file would truncate the other's rows when it flushes
+            if let Some((other, _)) = files.iter().find(|(_, file)| file.path == path) {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("`{}` is already the report for `{other}`", path.display()),
+                ));
+            }
+            fs::File::create(path)?;
+            let file = ReportFile {
+                path: path.to_path_buf(),
+                format,
+                buffer: Vec::new(),
+                has_header: false,
+                written: 0,
+            };
+            files.insert(type_name::<R>().to_string(), file);
+            Ok(())
+        }
+        /// Buffers `row` with the current time, writing the file's buffer out
+        /// once it reaches `FLUSH_THRESHOLD`. Panics if no report was
+        /// registered for `R`, or if the file can't be written.
+        fn send_report<R: Serialize + 'static>(&mut self, row: R) {
+            let time = self.get_current_time();
+            let file = self
+                .get_data_mut_in::<ReportPlugin>(DEFAULT_NAMESPACE)
+                .files
+                .get_mut(type_name::<R>())
+                .unwrap_or_else(|| panic!("No report registered for `{}`", type_name::<R>()));
+            file.push(time, &row).unwrap_or_else(|error| {
+                panic!(
+                    "Failed to serialize `{}` report row: {error}",
+                    type_name::<R>()
+                )
+            });
+            if file.buffer.len() >= FLUSH_THRESHOLD {
+                file.flush()
+                    .unwrap_or_else(|error| panic!("Failed to write report {error}"));
+            }
+        }
+        /// Writes every buffered row to its file.
+        fn flush_reports(&mut self) -> io::Result<()> {
+            self.get_data_mut_in::<ReportPlugin>(DEFAULT_NAMESPACE)
+                .flush()
+        }
+    }
+    impl<T: Context + ?Sized> ReportExt for T {}
+}
+
+use bool_plugin::BoolExt;
+use context::*;
+
+use crate::number_plugin::{NumberExt, NumberPlugin, SetNumber};
+use crate::random::RandomExt;
+use crate::report::{ReportExt, ReportFormat};
+
+define_rng!(ContactRng);
+define_rng!(DurationRng);
+
+#[derive(serde::Serialize)]
+struct ContactRow {
+    contact: u32,
+    setting: usize,
+    duration: f64,
+    recovered: bool,
+}
+
+#[derive(serde::Serialize)]
+struct NumberRow {
+    namespace: &'static str,
+    number: u32,
+}
+
+pub fn main() {
+    // Example usage
+    let mut context = BaseContext::new();
+    assert!(!context.get_bool());
+    assert_eq!(context.get_bool_as_number(), 0);
+    assert_eq!(context.get_number(), 0);
+    context.set_bool(true);
+    assert!(context.get_bool());
+    assert_eq!(context.get_bool_as_number(), 1);
+    context.set_number(2);
+    assert_eq!(context.get_number(), 2);
+
+    // What if the number were 3?
+    let mut overlay = OverlayContext::new(&mut context);
+    overlay.set_number(3);
+    assert_eq!(overlay.get_number(), 3);
+    overlay.discard();
+    assert_eq!(context.get_number(), 2);
+
+    let mut overlay = OverlayContext::new(&mut context);
+    overlay.set_bool(false);
+    overlay.commit();
+    assert_eq!(context.get_bool_as_number(), 0);
+
+    context.add_plan(1.0, |context| context.set_number(4));
+    context.execute();
+    assert_eq!(context.get_current_time(), 1.0);
+    assert_eq!(context.get_number(), 4);
+
+    // Two instances of the number plugin alongside the default one
+    NamespacedContext::new(&mut context, "flu").set_number(5);
+    NamespacedContext::new(&mut context, "covid").set_data::<NumberPlugin>(6);
+    assert_eq!(*context.get_data_in::<NumberPlugin>("flu"), 5);
+    assert_eq!(*context.get_data_in::<NumberPlugin>("covid"), 6);
+    assert_eq!(context.get_number(), 4);
+
+    // Reports are buffered until they are flushed or the context is finalized
+    let contacts_path = std::env::temp_dir().join("context-trait-global-contacts.csv");
+    let numbers_path = std::env::temp_dir().join("context-trait-global-numbers.jsonl");
+    context
+        .add_report::<ContactRow>(&contacts_path, ReportFormat::Csv)
+        .unwrap();
+    context
+        .add_report::<NumberRow>(&numbers_path, ReportFormat::Json)
+        .unwrap();
+    for namespace in ["flu", "covid"] {
+        let number = *context.get_data_in::<NumberPlugin>(namespace);
+        context.send_report(NumberRow { namespace, number });
+    }
+
+    // Random numbers from independent, reproducible streams
+    context.init_random(42);
+    let contact = context.sample_range(ContactRng, 0..100);
+    let setting = context.sample_weighted(ContactRng, &[0.7, 0.3]);
+    let duration = context.sample_distr(DurationRng, rand::distributions::Uniform::new(1.0, 5.0));
+    let recovered = context.sample_bool(DurationRng, 0.5);
+    context.send_report(ContactRow {
+        contact,
+        setting,
+        duration,
+        recovered,
+    });
+
+    context.flush_reports().unwrap();
+    for path in [&contacts_path, &numbers_path] {
+        print!("{}", std::fs::read_to_string(path).unwrap());
+        let _ = std::fs::remove_file(path);
+    }
+
+    // Save and resume
+    context.add_serializable_plan(2.0, SetNumber(7));
+    let path = std::env::temp_dir().join("context-trait-global-checkpoint.json");
+    context.save_checkpoint(&path).unwrap();
+    let mut resumed = BaseContext::load_checkpoint(&path).unwrap();
+    assert_eq!(resumed.get_current_time(), 1.0);
+    assert_eq!(*resumed.get_data_in::<NumberPlugin>("flu"), 5);
+    // Random number streams continue where they left off
+    assert_eq!(
+        resumed.sample_range(ContactRng, 0..100),
+        context.sample_range(ContactRng, 0..100)
+    );
+    resumed.execute();
+    assert_eq!(resumed.get_number(), 7);
+    let _ = std::fs::remove_file(path);
+}
+
+#[cfg(test)]
+mod test {
+    use super::context::{BaseContext, Context, DataPlugin};
+    use super::define_data_plugin;
+
+    define_data_plugin!(A, usize, |context| {
+        println!("Initializing A");
+        *context.get_data(B) + 1
+    });
+
+    define_data_plugin!(B, usize, |context| {
+        println!("Initializing B");
+        *context.get_data(C) + 1
+    });
+
+    define_data_plugin!(C, usize, |_context| {
+        println!("Initializing C");
+        0
     });
+    crate::register_clone_plugin!(C);
 
     #[test]
     fn test() {
@@ -200,6 +1550,22 @@ mod test {
         assert_eq!(*context.get_data(B), 1);
     }
 
+    #[test]
+    fn test_mut_initializes_dependencies() {
+        let mut context = BaseContext::new();
+
+        // A's initializer reads B, which reads C, none of them initialized yet
+        *context.get_data_mut(A) += 10;
+        assert_eq!(*context.get_data(A), 12);
+        assert_eq!(*context.get_data(B), 1);
+        assert_eq!(*context.get_data(C), 0);
+
+        // Already initialized dependencies are not initialized again
+        *context.get_data_mut(C) += 1;
+        assert_eq!(*context.get_data_mut(B), 1);
+        assert_eq!(*context.get_data_mut(A), 12);
+    }
+
     #[test]
     fn test_mut() {
         let mut context = BaseContext::new();
@@ -208,4 +1574,863 @@ mod test {
         assert_eq!(*context.get_data(A), 3);
         assert_eq!(*context.get_data(B), 2);
     }
+
+    define_data_plugin!(
+        #[derive(Debug)]
+        pub CounterPlugin<T: Clone>,
+        Vec<T>,
+        |_context| Vec::new(),
+        "Counts values of any type"
+    );
+
+    define_data_plugin!(
+        pub(crate) CountedNumbers,
+        usize,
+        |context| context.get_data(CounterPlugin::<u32>::new()).len()
+    );
+
+    #[test]
+    fn test_generic_plugin() {
+        let mut context = BaseContext::new();
+        context.get_data_mut(CounterPlugin::<u32>::new()).push(1);
+        context.get_data_mut(CounterPlugin::<u32>::new()).push(2);
+        context
+            .get_data_mut(CounterPlugin::<&str>::new())
+            .push("one");
+
+        assert_eq!(*context.get_data(CounterPlugin::<u32>::new()), vec![1, 2]);
+        assert_eq!(*context.get_data(CounterPlugin::<&str>::new()), vec!["one"]);
+        assert_eq!(*context.get_data(CountedNumbers), 2);
+        assert_eq!(
+            *context.get_data(CounterPlugin::<bool>::default()),
+            Vec::<bool>::new()
+        );
+    }
+
+    thread_local! {
+        static FINALIZED: std::cell::RefCell<Vec<(&'static str, usize)>> =
+            const { std::cell::RefCell::new(Vec::new()) };
+    }
+
+    struct FinalA;
+    impl DataPlugin for FinalA {
+        type DataContainer = usize;
+        fn init(context: &impl Context) -> Self::DataContainer {
+            *context.get_data(FinalB) + 1
+        }
+        fn finalize(data: &mut Self::DataContainer, context: &impl Context) {
+            // Dependencies are still available
+            *data += *context.get_data(FinalB);
+            FINALIZED.with_borrow_mut(|finalized| finalized.push(("A", *data)));
+        }
+    }
+
+    struct FinalB;
+    impl DataPlugin for FinalB {
+        type DataContainer = usize;
+        fn init(_context: &impl Context) -> Self::DataContainer {
+            10
+        }
+        fn finalize(data: &mut Self::DataContainer, _context: &impl Context) {
+            FINALIZED.with_borrow_mut(|finalized| finalized.push(("B", *data)));
+        }
+    }
+
+    #[ctor::ctor]
+    fn register_final_plugins() {
+        super::context::add_plugin_to_registry::<FinalA>();
+        super::context::add_plugin_to_registry::<FinalB>();
+    }
+
+    #[test]
+    fn test_finalize_in_reverse_initialization_order() {
+        FINALIZED.with_borrow_mut(Vec::clear);
+        let mut context = BaseContext::new();
+        context.get_data(FinalA);
+        context.finalize();
+        assert_eq!(
+            FINALIZED.with_borrow(Clone::clone),
+            vec![("A", 21), ("B", 10)]
+        );
+        // Finalizing again (or dropping) is a no-op
+        context.finalize();
+        drop(context);
+        assert_eq!(FINALIZED.with_borrow(Vec::len), 2);
+    }
+
+    #[test]
+    fn test_finalize_on_drop() {
+        FINALIZED.with_borrow_mut(Vec::clear);
+        {
+            let mut context = BaseContext::new();
+            *context.get_data_mut(FinalB) = 5;
+        }
+        assert_eq!(FINALIZED.with_borrow(Clone::clone), vec![("B", 5)]);
+    }
+
+    #[test]
+    fn test_overlay() {
+        use super::context::OverlayContext;
+        use super::{BoolExt, NumberExt};
+
+        let mut context = BaseContext::new();
+        context.set_number(1);
+
+        let mut overlay = OverlayContext::new(&mut context);
+        overlay.set_number(5);
+        overlay.set_bool(true);
+        assert_eq!(overlay.get_number(), 5);
+        assert_eq!(overlay.get_bool_as_number(), 1);
+        // Unmodified plugins read through to the base context
+        assert_eq!(*overlay.get_data(C), 0);
+        overlay.discard();
+        assert_eq!(context.get_number(), 1);
+        assert!(!context.get_bool());
+
+        let mut overlay = OverlayContext::new(&mut context);
+        *overlay.get_data_mut(C) += 2;
+        // Nested overlays are committed into their parent
+        let mut nested = OverlayContext::new(&mut overlay);
+        nested.set_number(7);
+        // A is initialized in the base context, which doesn't see C's new value
+        assert_eq!(*nested.get_data(A), 2);
+        nested.commit();
+        assert_eq!(overlay.get_number(), 7);
+        overlay.commit();
+        assert_eq!(context.get_number(), 7);
+        assert_eq!(*context.get_data(C), 2);
+
+        // Plans added through an overlay are only scheduled on commit
+        let mut overlay = OverlayContext::new(&mut context);
+        overlay.add_plan(1.0, |context| context.set_number(8));
+        overlay.discard();
+        let mut overlay = OverlayContext::new(&mut context);
+        overlay.add_plan(2.0, |context| context.set_number(9));
+        overlay.commit();
+        context.execute();
+        assert_eq!(context.get_current_time(), 2.0);
+        assert_eq!(context.get_number(), 9);
+    }
+
+    // Data that can't be cloned, so overlays can replace it but not mutate it
+    struct Handle(usize);
+    define_data_plugin!(Unclonable, Handle, |_context| Handle(0));
+
+    #[test]
+    fn test_overlay_replaces_unclonable_data() {
+        use super::context::OverlayContext;
+
+        let mut context = BaseContext::new();
+        let mut overlay = OverlayContext::new(&mut context);
+        overlay.set_data::<Unclonable>(Handle(1));
+        overlay.get_data_mut(Unclonable).0 += 1;
+        assert_eq!(overlay.get_data(Unclonable).0, 2);
+        overlay.commit();
+        assert_eq!(context.get_data(Unclonable).0, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't be mutated through an overlay: it isn't registered")]
+    fn test_overlay_rejects_mutating_unclonable_data() {
+        use super::context::OverlayContext;
+
+        let mut context = BaseContext::new();
+        let mut overlay = OverlayContext::new(&mut context);
+        overlay.get_data_mut(Unclonable).0 += 1;
+    }
+
+    /// Reports once a day for three days, without the model scheduling it.
+    struct DailyReport;
+    impl DataPlugin for DailyReport {
+        type DataContainer = Vec<f64>;
+        fn init(_context: &impl Context) -> Self::DataContainer {
+            Vec::new()
+        }
+        fn on_initialized(context: &mut impl Context) {
+            fn report(context: &mut BaseContext) {
+                let time = context.get_current_time();
+                context.get_data_mut(DailyReport).push(time);
+                if time < 3.0 {
+                    context.add_plan(time + 1.0, report);
+                }
+            }
+            let time = context.get_current_time();
+            context.add_plan(time + 1.0, report);
+        }
+    }
+
+    #[test]
+    fn test_on_initialized_schedules_plans() {
+        let mut context = BaseContext::new();
+        // Initialized through a shared reference; the hook runs at the next safe point
+        assert!(context.get_data(DailyReport).is_empty());
+        context.execute();
+        assert_eq!(*context.get_data(DailyReport), vec![1.0, 2.0, 3.0]);
+        assert_eq!(context.get_current_time(), 3.0);
+    }
+
+    #[test]
+    fn test_on_initialized_in_namespace() {
+        use super::context::NamespacedContext;
+
+        let mut context = BaseContext::new();
+        NamespacedContext::new(&mut context, "flu").get_data(DailyReport);
+        context.execute();
+        NamespacedContext::new(&mut context, "covid").get_data(DailyReport);
+        context.execute();
+        // The hook and the plans it schedules stay in the plugin's namespace
+        assert_eq!(
+            *context.get_data_in::<DailyReport>("flu"),
+            vec![1.0, 2.0, 3.0]
+        );
+        assert_eq!(*context.get_data_in::<DailyReport>("covid"), vec![4.0]);
+        assert!(context.get_data(DailyReport).is_empty());
+    }
+
+    define_data_plugin!(HookLog, Vec<&'static str>, |_context| Vec::new());
+
+    struct HookA;
+    impl DataPlugin for HookA {
+        type DataContainer = ();
+        fn init(context: &impl Context) -> Self::DataContainer {
+            context.get_data(HookB);
+        }
+        fn on_initialized(context: &mut impl Context) {
+            context.get_data_mut(HookLog).push("A");
+        }
+    }
+
+    struct HookB;
+    impl DataPlugin for HookB {
+        type DataContainer = ();
+        fn init(_context: &impl Context) -> Self::DataContainer {}
+        fn on_initialized(context: &mut impl Context) {
+            context.get_data_mut(HookLog).push("B");
+        }
+    }
+
+    #[test]
+    fn test_on_initialized_order() {
+        let mut context = BaseContext::new();
+        context.get_data(HookA);
+        assert!(context.get_data(HookLog).is_empty());
+        // Hooks run in initialization order before the reference is returned
+        assert_eq!(*context.get_data_mut(HookLog), vec!["B", "A"]);
+        context.get_data_mut(HookA);
+        assert_eq!(*context.get_data(HookLog), vec!["B", "A"]);
+    }
+
+    #[test]
+    fn test_namespaces() {
+        use super::context::{NamespacedContext, OverlayContext};
+        use super::{BoolExt, NumberExt};
+
+        let mut context = BaseContext::new();
+        context.set_number(1);
+        let mut flu = NamespacedContext::new(&mut context, "flu");
+        assert_eq!(flu.get_number(), 0);
+        flu.set_number(2);
+        flu.set_bool(true);
+        assert_eq!(flu.get_bool_as_number(), 1);
+        NamespacedContext::new(&mut context, "covid").set_number(3);
+
+        assert_eq!(context.get_number(), 1);
+        assert!(!context.get_bool());
+        assert_eq!(*context.get_data_in::<super::NumberPlugin>("flu"), 2);
+        assert_eq!(*context.get_data_in::<super::NumberPlugin>("covid"), 3);
+
+        // Initializers read other plugins in the namespace being initialized
+        *context.get_data_mut(C) = 5;
+        assert_eq!(*context.get_data_in::<A>("flu"), 2);
+        assert_eq!(*context.get_data_in::<C>("flu"), 0);
+        assert_eq!(*context.get_data(A), 7);
+
+        // Overlays keep namespaces apart too
+        let mut overlay = OverlayContext::new(&mut context);
+        NamespacedContext::new(&mut overlay, "flu").set_number(4);
+        assert_eq!(overlay.get_number(), 1);
+        overlay.commit();
+        assert_eq!(*context.get_data_in::<super::NumberPlugin>("flu"), 4);
+        assert_eq!(context.get_number(), 1);
+    }
+
+    define_data_plugin!(Transmissibility, f64, |_context| 1.0);
+
+    define_data_plugin!(InfectionRate, f64, |context| {
+        *context.get_data(Transmissibility) * 2.0
+    });
+
+    #[test]
+    fn test_initializer_reads_own_namespace() {
+        use super::context::NamespacedContext;
+
+        let mut context = BaseContext::new();
+        context.set_data_in::<Transmissibility>("flu", 2.5);
+        // Even when first accessed from another namespace's plan
+        NamespacedContext::new(&mut context, "covid").add_plan(1.0, |context| {
+            assert_eq!(*context.get_data_in::<InfectionRate>("flu"), 5.0);
+            assert_eq!(*context.get_data(InfectionRate), 2.0);
+        });
+        context.execute();
+        assert_eq!(*context.get_data(InfectionRate), 2.0);
+    }
+
+    #[test]
+    fn test_overlay_in_namespaced_plan() {
+        use super::NumberExt;
+        use super::context::{NamespacedContext, OverlayContext};
+
+        let mut context = BaseContext::new();
+        NamespacedContext::new(&mut context, "flu").add_plan(1.0, |context| {
+            context.set_number(1);
+            // The overlay reads and writes the plan's namespace
+            let mut overlay = OverlayContext::new(context);
+            assert_eq!(overlay.get_number(), 1);
+            overlay.set_number(2);
+            overlay.commit();
+        });
+        context.execute();
+        assert_eq!(*context.get_data_in::<super::NumberPlugin>("flu"), 2);
+        assert_eq!(context.get_number(), 0);
+    }
+
+    struct FinalNamespaced;
+    impl DataPlugin for FinalNamespaced {
+        type DataContainer = usize;
+        fn init(_context: &impl Context) -> Self::DataContainer {
+            0
+        }
+        fn finalize(data: &mut Self::DataContainer, context: &impl Context) {
+            // Reads the data of `FinalB` in this plugin's namespace
+            *data += *context.get_data(FinalB);
+            FINALIZED.with_borrow_mut(|finalized| finalized.push(("N", *data)));
+        }
+    }
+
+    #[test]
+    fn test_finalize_in_namespace() {
+        FINALIZED.with_borrow_mut(Vec::clear);
+        let mut context = BaseContext::new();
+        context.set_data_in::<FinalB>("flu", 1);
+        context.set_data_in::<FinalNamespaced>("flu", 100);
+        context.finalize();
+        assert_eq!(
+            FINALIZED.with_borrow(Clone::clone),
+            vec![("N", 101), ("B", 1)]
+        );
+    }
+
+    #[test]
+    fn test_finalize_each_namespace() {
+        FINALIZED.with_borrow_mut(Vec::clear);
+        let mut context = BaseContext::new();
+        context.set_data_in::<FinalB>("first", 1);
+        context.get_data_in::<FinalB>("second");
+        context.finalize();
+        assert_eq!(
+            FINALIZED.with_borrow(Clone::clone),
+            vec![("B", 10), ("B", 1)]
+        );
+    }
+
+    struct FinalSelf;
+    impl DataPlugin for FinalSelf {
+        type DataContainer = usize;
+        fn init(_context: &impl Context) -> Self::DataContainer {
+            0
+        }
+        fn finalize(_data: &mut Self::DataContainer, context: &impl Context) {
+            context.get_data(FinalSelf);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "while it was being finalized in namespace \"flu\"")]
+    fn test_finalizer_reading_own_plugin_panics() {
+        let mut context = BaseContext::new();
+        context.get_data_in::<FinalSelf>("flu");
+        context.finalize();
+    }
+
+    #[test]
+    #[should_panic(expected = "during finalization but was never initialized in namespace \"flu\"")]
+    fn test_finalizer_reading_uninitialized_plugin_panics() {
+        let mut context = BaseContext::new();
+        // `FinalB` is only initialized in the default namespace
+        context.get_data(FinalB);
+        context.set_data_in::<FinalNamespaced>("flu", 0);
+        context.finalize();
+    }
+
+    mod checkpoint {
+        use super::super::context::checkpoint::{CheckpointError, SerializablePlan};
+        use super::super::context::{BaseContext, Context, DataPlugin};
+        use super::super::{register_checkpoint_plan, register_checkpoint_plugin};
+        use serde::{Deserialize, Serialize};
+
+        /// Records when each `Tick` ran; schedules the first tick itself.
+        struct Ticks;
+        impl DataPlugin for Ticks {
+            type DataContainer = Vec<f64>;
+            fn init(_context: &impl Context) -> Self::DataContainer {
+                Vec::new()
+            }
+            fn on_initialized(context: &mut impl Context) {
+                context.add_plan(1.0, |context| {
+                    context.add_serializable_plan(1.0, Tick { left: 4 })
+                });
+            }
+        }
+        register_checkpoint_plugin!(Ticks, "ticks");
+
+        #[derive(Serialize, Deserialize)]
+        struct Tick {
+            left: u32,
+        }
+        impl SerializablePlan for Tick {
+            const NAME: &'static str = "tick";
+
+            fn run(self, context: &mut BaseContext) {
+                let time = context.get_current_time();
+                context.get_data_mut(Ticks).push(time);
+                if self.left > 1 {
+                    context.add_serializable_plan(
+                        time + 1.0,
+                        Tick {
+                            left: self.left - 1,
+                        },
+                    );
+                }
+            }
+        }
+        register_checkpoint_plan!(Tick);
+
+        fn checkpoint_path(name: &str) -> std::path::PathBuf {
+            std::env::temp_dir().join(format!(
+                "context-trait-global-{}-{name}.json",
+                std::process::id()
+            ))
+        }
+
+        #[test]
+        fn test_resume_from_checkpoint() {
+            let path = checkpoint_path("resume");
+            let mut context = BaseContext::new();
+            context.get_data(Ticks);
+            // Plans are taken off the queue before they run, so a plan can
+            // checkpoint everything that is still to come
+            let checkpoint = path.clone();
+            context.add_plan(2.0, move |context| {
+                context.save_checkpoint(checkpoint).unwrap()
+            });
+            context.execute();
+            assert_eq!(*context.get_data(Ticks), vec![1.0, 2.0, 3.0, 4.0]);
+
+            let mut resumed = BaseContext::load_checkpoint(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(resumed.get_current_time(), 2.0);
+            assert_eq!(*resumed.get_data(Ticks), vec![1.0]);
+
+            // `on_initialized` is not run again for restored plugins
+            resumed.execute();
+            assert_eq!(*resumed.get_data(Ticks), *context.get_data(Ticks));
+        }
+
+        #[test]
+        fn test_checkpoint_pending_hooks() {
+            use super::super::context::NamespacedContext;
+
+            let path = checkpoint_path("hooks");
+            let mut context = BaseContext::new();
+            // Initialized through shared references, so the hooks are still
+            // queued when the checkpoint is saved
+            context.get_data(Ticks);
+            NamespacedContext::new(&mut context, "flu").get_data(Ticks);
+            context.save_checkpoint(&path).unwrap();
+
+            // Saved under the registered name, not the type's path
+            let contents = std::fs::read_to_string(&path).unwrap();
+            assert!(contents.contains(r#""plugin": "ticks""#), "{contents}");
+
+            let mut resumed = BaseContext::load_checkpoint(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            resumed.execute();
+            assert_eq!(*resumed.get_data(Ticks), vec![1.0, 2.0, 3.0, 4.0]);
+            assert_eq!(
+                *resumed.get_data_in::<Ticks>("flu"),
+                vec![1.0, 2.0, 3.0, 4.0]
+            );
+        }
+
+        #[test]
+        fn test_resume_namespaced_plans() {
+            use super::super::context::NamespacedContext;
+
+            let path = checkpoint_path("namespaced");
+            let mut context = BaseContext::new();
+            NamespacedContext::new(&mut context, "flu").get_data(Ticks);
+            let checkpoint = path.clone();
+            context.add_plan(2.0, move |context| {
+                context.save_checkpoint(checkpoint).unwrap()
+            });
+            context.execute();
+
+            // Restored plans keep their namespace
+            let mut resumed = BaseContext::load_checkpoint(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            resumed.execute();
+            assert_eq!(
+                *resumed.get_data_in::<Ticks>("flu"),
+                vec![1.0, 2.0, 3.0, 4.0]
+            );
+            assert!(resumed.get_data(Ticks).is_empty());
+        }
+
+        #[test]
+        fn test_checkpoint_errors() {
+            let path = checkpoint_path("errors");
+            let mut context = BaseContext::new();
+            context.add_plan(1.0, |_| {});
+            assert_eq!(
+                context.save_checkpoint(&path),
+                Err(CheckpointError::UnserializablePlan { time: 1.0 })
+            );
+
+            std::fs::write(
+                &path,
+                r#"{"time": 0.0, "plugins": [{"plugin": "Unknown", "namespace": "", "data": 1}], "plans": [], "pending_hooks": []}"#,
+            )
+            .unwrap();
+            assert_eq!(
+                BaseContext::load_checkpoint(&path).err(),
+                Some(CheckpointError::Unregistered {
+                    name: "Unknown".to_string()
+                })
+            );
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    mod random {
+        use super::super::context::{BaseContext, OverlayContext};
+        use super::super::define_rng;
+        use super::super::random::RandomExt;
+
+        define_rng!(FirstRng);
+        define_rng!(SecondRng);
+
+        fn draws(context: &mut impl RandomExt) -> Vec<u32> {
+            (0..5)
+                .map(|_| context.sample_range(FirstRng, 0..1000))
+                .collect()
+        }
+
+        #[test]
+        fn test_streams_are_reproducible() {
+            let mut context = BaseContext::new();
+            context.init_random(1);
+            let first = draws(&mut context);
+
+            // Using another stream doesn't change this one's numbers
+            let mut other = BaseContext::new();
+            other.init_random(1);
+            let mut interleaved = Vec::new();
+            for _ in 0..5 {
+                other.sample_bool(SecondRng, 0.5);
+                interleaved.push(other.sample_range(FirstRng, 0..1000));
+            }
+            assert_eq!(interleaved, first);
+
+            // Re-seeding restarts every stream
+            context.init_random(1);
+            assert_eq!(draws(&mut context), first);
+            context.init_random(2);
+            assert_ne!(draws(&mut context), first);
+
+            // Streams with different names are seeded differently
+            context.init_random(1);
+            let second = (0..5)
+                .map(|_| context.sample_range(SecondRng, 0..1000))
+                .collect::<Vec<_>>();
+            assert_ne!(second, first);
+        }
+
+        #[test]
+        fn test_namespaces_share_streams() {
+            use super::super::context::NamespacedContext;
+
+            let mut context = BaseContext::new();
+            context.init_random(1);
+            let expected = [draws(&mut context), draws(&mut context)];
+
+            // The seed set through one namespace applies to all of them, and
+            // each namespace continues the same streams
+            let mut other = BaseContext::new();
+            NamespacedContext::new(&mut other, "flu").init_random(1);
+            let flu = draws(&mut NamespacedContext::new(&mut other, "flu"));
+            let covid = draws(&mut NamespacedContext::new(&mut other, "covid"));
+            assert_eq!([flu, covid], expected);
+
+            context.init_random(999);
+            NamespacedContext::new(&mut other, "covid").init_random(999);
+            assert_eq!(
+                draws(&mut NamespacedContext::new(&mut other, "flu")),
+                draws(&mut context)
+            );
+        }
+
+        #[test]
+        fn test_sampling() {
+            let mut context = BaseContext::new();
+            for _ in 0..10 {
+                assert_eq!(context.sample_weighted(FirstRng, &[0.0, 2.0, 0.0]), 1);
+                assert!(context.sample_bool(FirstRng, 1.0));
+                assert!(!context.sample_bool(FirstRng, 0.0));
+                let value =
+                    context.sample_distr(SecondRng, rand::distributions::Uniform::new(1.0, 2.0));
+                assert!((1.0..2.0).contains(&value));
+            }
+        }
+
+        #[test]
+        fn test_overlay_draws_are_discarded() {
+            let mut context = BaseContext::new();
+            let mut overlay = OverlayContext::new(&mut context);
+            let counterfactual = draws(&mut overlay);
+            overlay.discard();
+            assert_eq!(draws(&mut context), counterfactual);
+        }
+
+        #[test]
+        #[should_panic(expected = "Invalid weights [0.0, 0.0]")]
+        fn test_invalid_weights() {
+            BaseContext::new().sample_weighted(FirstRng, &[0.0, 0.0]);
+        }
+    }
+
+    mod report {
+        use super::super::context::{BaseContext, Context, OverlayContext};
+        use super::super::report::{FLUSH_THRESHOLD, ReportExt, ReportFormat};
+        use serde::Serialize;
+        use std::{fs, path::PathBuf};
+
+        #[derive(Serialize)]
+        struct Infection {
+            person: u32,
+            setting: &'static str,
+        }
+
+        fn report_path(name: &str) -> PathBuf {
+            std::env::temp_dir().join(format!(
+                "context-trait-global-{}-{name}",
+                std::process::id()
+            ))
+        }
+
+        fn infect(context: &mut BaseContext, time: f64, person: u32, setting: &'static str) {
+            context.add_plan(time, move |context| {
+                context.send_report(Infection { person, setting })
+            });
+        }
+
+        #[test]
+        fn test_csv_report() {
+            let path = report_path("infections.csv");
+            let mut context = BaseContext::new();
+            context
+                .add_report::<Infection>(&path, ReportFormat::Csv)
+                .unwrap();
+            infect(&mut context, 1.0, 1, "home");
+            infect(&mut context, 2.5, 2, "work, floor 2");
+            context.execute();
+            // Rows are buffered until the context is finalized
+            assert_eq!(fs::read_to_string(&path).unwrap(), "");
+            drop(context);
+            assert_eq!(
+                fs::read_to_string(&path).unwrap(),
+                "time,person,setting\n1.0,1,home\n2.5,2,\"work, floor 2\"\n"
+            );
+            fs::remove_file(&path).unwrap();
+        }
+
+        #[test]
+        fn test_json_report() {
+            let path = report_path("infections.jsonl");
+            let mut context = BaseContext::new();
+            context
+                .add_report::<Infection>(&path, ReportFormat::Json)
+                .unwrap();
+            infect(&mut context, 1.0, 1, "home");
+            context.execute();
+            context.flush_reports().unwrap();
+            infect(&mut context, 2.0, 2, "work");
+            context.execute();
+            let first_flush = fs::read_to_string(&path).unwrap();
+            assert_eq!(
+                first_flush,
+                "{\"time\":1.0,\"person\":1,\"setting\":\"home\"}\n"
+            );
+            context.finalize();
+            assert_eq!(
+                fs::read_to_string(&path).unwrap(),
+                first_flush + "{\"time\":2.0,\"person\":2,\"setting\":\"work\"}\n"
+            );
+            fs::remove_file(&path).unwrap();
+        }
+
+        #[test]
+        fn test_large_reports_are_flushed() {
+            let path = report_path("large.jsonl");
+            let mut context = BaseContext::new();
+            context
+                .add_report::<Infection>(&path, ReportFormat::Json)
+                .unwrap();
+            let row = "{\"time\":0.0,\"person\":1,\"setting\":\"home\"}\n";
+            let rows = FLUSH_THRESHOLD.div_ceil(row.len());
+            for _ in 0..rows {
+                context.send_report(Infection {
+                    person: 1,
+                    setting: "home",
+                });
+            }
+            // Written without waiting for a flush, a whole row at a time
+            assert_eq!(fs::read_to_string(&path).unwrap(), row.repeat(rows));
+            context.send_report(Infection {
+                person: 1,
+                setting: "home",
+            });
+            context.finalize();
+            assert_eq!(fs::read_to_string(&path).unwrap(), row.repeat(rows + 1));
+            fs::remove_file(&path).unwrap();
+        }
+
+        #[test]
+        fn test_resume_reports() {
+            let path = report_path("resumed.csv");
+            let checkpoint = report_path("reports-checkpoint.json");
+            let row = |context: &mut BaseContext, person| {
+                context.send_report(Infection {
+                    person,
+                    setting: "home",
+                })
+            };
+            let mut context = BaseContext::new();
+            context
+                .add_report::<Infection>(&path, ReportFormat::Csv)
+                .unwrap();
+            row(&mut context, 1);
+            context.flush_reports().unwrap();
+            row(&mut context, 2);
+            context.save_checkpoint(&checkpoint).unwrap();
+            // Rows written after the checkpoint, say by a run that then crashed
+            row(&mut context, 3);
+            context.finalize();
+
+            let mut resumed = BaseContext::load_checkpoint(&checkpoint).unwrap();
+            fs::remove_file(&checkpoint).unwrap();
+            // Repeating the setup keeps the restored report
+            resumed
+                .add_report::<Infection>(&path, ReportFormat::Csv)
+                .unwrap();
+            row(&mut resumed, 4);
+            resumed.finalize();
+            assert_eq!(
+                fs::read_to_string(&path).unwrap(),
+                "time,person,setting\n0.0,1,home\n0.0,2,home\n0.0,4,home\n"
+            );
+            fs::remove_file(&path).unwrap();
+        }
+
+        #[test]
+        fn test_namespaced_reports() {
+            use super::super::context::NamespacedContext;
+
+            let path = report_path("namespaced.jsonl");
+            let mut context = BaseContext::new();
+            NamespacedContext::new(&mut context, "flu")
+                .add_report::<Infection>(&path, ReportFormat::Json)
+                .unwrap();
+            NamespacedContext::new(&mut context, "covid").send_report(Infection {
+                person: 1,
+                setting: "home",
+            });
+            NamespacedContext::new(&mut context, "flu")
+                .flush_reports()
+                .unwrap();
+            assert_eq!(
+                fs::read_to_string(&path).unwrap(),
+                "{\"time\":0.0,\"person\":1,\"setting\":\"home\"}\n"
+            );
+            fs::remove_file(&path).unwrap();
+        }
+
+        #[test]
+        fn test_overlay_reports_are_discarded() {
+            let path = report_path("overlay.jsonl");
+            let mut context = BaseContext::new();
+            context
+                .add_report::<Infection>(&path, ReportFormat::Json)
+                .unwrap();
+            let mut overlay = OverlayContext::new(&mut context);
+            overlay.send_report(Infection {
+                person: 1,
+                setting: "home",
+            });
+            overlay.discard();
+            context.finalize();
+            assert_eq!(fs::read_to_string(&path).unwrap(), "");
+            fs::remove_file(&path).unwrap();
+        }
+
+        #[test]
+        fn test_flushed_overlay_reports_are_discarded() {
+            let path = report_path("overlay-flushed.jsonl");
+            let mut context = BaseContext::new();
+            context
+                .add_report::<Infection>(&path, ReportFormat::Json)
+                .unwrap();
+            let mut overlay = OverlayContext::new(&mut context);
+            // Enough rows that the overlay's copy of the report is flushed
+            while fs::read_to_string(&path).unwrap().is_empty() {
+                overlay.send_report(Infection {
+                    person: 1,
+                    setting: "home",
+                });
+            }
+            overlay.discard();
+            context.finalize();
+            assert_eq!(fs::read_to_string(&path).unwrap(), "");
+            fs::remove_file(&path).unwrap();
+        }
+
+        #[derive(Serialize)]
+        struct Recovery {
+            person: u32,
+        }
+
+        #[test]
+        fn test_report_errors() {
+            let path = report_path("errors.csv");
+            let mut context = BaseContext::new();
+            context
+                .add_report::<Infection>(&path, ReportFormat::Csv)
+                .unwrap();
+            let error = context
+                .add_report::<Infection>(&path, ReportFormat::Json)
+                .unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::AlreadyExists);
+            let error = context
+                .add_report::<Recovery>(&path, ReportFormat::Csv)
+                .unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::AlreadyExists);
+            assert!(error.to_string().contains("Infection"), "{error}");
+            fs::remove_file(&path).unwrap();
+        }
+
+        #[test]
+        #[should_panic(expected = "No report registered for `u32`")]
+        fn test_unregistered_report() {
+            BaseContext::new().send_report(1u32);
+        }
+    }
 }